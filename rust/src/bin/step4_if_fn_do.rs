@@ -249,6 +249,7 @@ fn special_fn(list: &mut Vec<MalType>, repl_env: &Env) -> MalResult {
             args,
             body: vec![body],
             is_macro: false,
+            name: None,
         }))
     } else {
         Err(MalError::WrongArguments(format!(
@@ -279,7 +280,7 @@ mod tests {
     fn test_fn() {
         let mut repl_env = top_repl_env();
         let result = rep("(fn* [a] a)".to_string(), &mut repl_env).unwrap();
-        assert_eq!("#<function>", result);
+        assert_eq!("#<fn [a]>", result);
         let result = rep("((fn* [a] a) 7)".to_string(), &mut repl_env).unwrap();
         assert_eq!("7", result);
         let result = rep("((fn* [a b] (+ a b)) 2 3)".to_string(), &mut repl_env).unwrap();
@@ -266,6 +266,7 @@ fn special_fn(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
             args,
             body: vec![body],
             is_macro: false,
+            name: None,
         })))
     } else {
         Err(MalError::WrongArguments(format!(
@@ -250,6 +250,10 @@ fn process_special_form(ast: &mut MalType, repl_env: Env) -> TailPositionResult
 
 fn special_def(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
     let name = vec.remove(0);
+    // core.mal gives its definitions docstrings, which this step ignores.
+    if vec.len() == 2 && vec[0].is_string() {
+        vec.remove(0);
+    }
     if let Some(sym) = name.symbol_val() {
         let val = eval(vec.remove(0), repl_env.clone())?;
         repl_env.set(sym, val.clone());
@@ -264,6 +268,10 @@ fn special_def(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
 
 fn special_defmacro(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
     let name = vec.remove(0);
+    // core.mal gives its definitions docstrings, which this step ignores.
+    if vec.len() == 2 && vec[0].is_string() {
+        vec.remove(0);
+    }
     if let Some(sym) = name.symbol_val() {
         let mut val = eval(vec.remove(0), repl_env.clone())?;
         if val.is_lambda() {
@@ -356,6 +364,7 @@ fn special_fn(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
             args,
             body: vec![body],
             is_macro: false,
+            name: None,
         })))
     } else {
         Err(MalError::WrongArguments(format!(
@@ -3,34 +3,55 @@
 extern crate mal_rust;
 
 use mal_rust::env::Env;
-use mal_rust::printer::pr_str;
+use mal_rust::printer::{pr_str_with, PrintOptions};
+use mal_rust::pprint::{pretty_str, PrettyOptions};
 use mal_rust::reader::read_str;
 use mal_rust::readline::Readline;
 use mal_rust::types::*;
-use mal_rust::core::NS;
+use mal_rust::core::{call_data, NS};
 use mal_rust::util::*;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::io::{self, IsTerminal, Read};
+use std::panic::{self, AssertUnwindSafe};
 use std::process;
 
+/// The standard library from `core.mal`, evaluated at startup unless
+/// `--no-stdlib` is passed.
+const STDLIB: &str = include_str!("../../../core.mal");
+
 fn main() {
-    let mut readline = Readline::new("user> ");
-    let repl_env = top_repl_env();
-    let args: Vec<_> = env::args().collect();
-    if args.len() > 1 {
-        let result = rep(
-            "(load-file \"".to_string() + &args[1] + "\")",
-            repl_env.clone(),
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let no_stdlib = !args.is_empty() && args[0] == "--no-stdlib";
+    if no_stdlib {
+        args.remove(0);
+    }
+    let repl_env = if no_stdlib {
+        bare_repl_env()
+    } else {
+        top_repl_env()
+    };
+    if !args.is_empty() {
+        repl_env.set(
+            "*ARGV*",
+            MalType::list(args[1..].iter().map(|a| MalType::string(a.clone())).collect()),
         );
-        match result {
-            Err(err) => {
-                println!("{}", err);
-                process::exit(1);
-            }
-            _ => process::exit(0),
+        exit_with(rep(
+            "(load-file \"".to_string() + &args[0] + "\")",
+            repl_env.clone(),
+        ));
+    }
+    if !io::stdin().is_terminal() {
+        // Piped in, so there's no one to prompt: run it as a program.
+        let mut program = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut program) {
+            println!("{}", MalError::from(err));
+            process::exit(1);
         }
+        exit_with(rep(format!("(do {}\n)", program), repl_env.clone()));
     }
+    let mut readline = Readline::new("user> ");
     rep(
         "(println (str \"Mal [\" *host-language* \"]\"))",
         repl_env.clone(),
@@ -39,11 +60,19 @@ fn main() {
         match readline.get() {
             Some(line) => {
                 if line.len() > 0 {
-                    let result = rep(line, repl_env.clone());
+                    // A panic is an interpreter bug, but it shouldn't take
+                    // the whole session down with it.
+                    let env = repl_env.clone();
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| rep(line, env)));
                     match result {
-                        Ok(str) => println!("{}", str),
-                        Err(MalError::BlankLine) => {}
-                        Err(err) => println!("{}", err),
+                        Ok(Ok(str)) => println!("{}", str),
+                        Ok(Err(MalError::BlankLine)) => {}
+                        Ok(Err(MalError::Exit(status))) => {
+                            readline.save_history();
+                            process::exit(status);
+                        }
+                        Ok(Err(err)) => println!("{}", err),
+                        Err(_) => println!("Internal error: the evaluator panicked"),
                     }
                 }
             }
@@ -53,7 +82,27 @@ fn main() {
     readline.save_history();
 }
 
+/// Exits after running a program, with the status passed to `exit`, or 1
+/// after printing an error.
+fn exit_with(result: Result<String, MalError>) -> ! {
+    match result {
+        Err(MalError::Exit(status)) => process::exit(status),
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+        _ => process::exit(0),
+    }
+}
+
+/// The REPL environment with the standard library loaded.
 fn top_repl_env() -> Env {
+    let repl_env = bare_repl_env();
+    rep(format!("(do {})", STDLIB), repl_env.clone()).expect("could not load the standard library");
+    repl_env
+}
+
+fn bare_repl_env() -> Env {
     let repl_env = Env::new(None);
     for (name, func) in NS.iter() {
         repl_env.set(
@@ -71,44 +120,37 @@ fn top_repl_env() -> Env {
             env: Some(repl_env.clone()),
         }),
     );
-    let argv: Vec<_> = env::args().collect();
-    repl_env.set(
-        "*ARGV*",
-        MalType::list(if argv.len() >= 3 {
-            argv[2..]
-                .iter()
-                .map(|a| MalType::string(a.clone()))
-                .collect()
-        } else {
-            vec![]
-        }),
-    );
+    repl_env.set("*ARGV*", MalType::list(vec![]));
     repl_env.set("*host-language*", MalType::string("Rust"));
+    repl_env.set_dynamic("*print-pretty*", MalType::bool_false());
+    repl_env.set_dynamic("*print-length*", MalType::nil());
+    repl_env.set_dynamic("*print-level*", MalType::nil());
+    repl_env.set_dynamic("*data-readers*", MalType::hashmap(BTreeMap::new()));
+    repl_env.set_dynamic("*out*", MalType::keyword("stdout"));
+    repl_env.set_dynamic("*err*", MalType::keyword("stderr"));
+    repl_env.set_dynamic("*in*", MalType::keyword("stdin"));
     repl_env.set("*gensym-auto-incr*", MalType::atom(MalType::number(1)));
-    rep("(def! not (fn* (a) (if a false true)))", repl_env.clone()).expect("could not define not");
     rep(
         "(def! load-file (fn* (f) (eval (read-string (str \"(do \" (slurp f) \")\")))))",
         repl_env.clone(),
     ).expect("could not define load-file");
-    rep(
-        "(defmacro! cond (fn* (& xs) (if (> (count xs) 0) (list 'if (first xs) (if (> (count xs) 1) (nth xs 1) (throw \"odd number of forms to cond\")) (cons 'cond (rest (rest xs)))))))",
-        repl_env.clone()
-       ).expect("could not define macro cond");
-    rep(
-        "(defmacro! or (fn* (& xs) (if (empty? xs) nil (if (= 1 (count xs)) (first xs) (let* (condvar (gensym)) `(let* (~condvar ~(first xs)) (if ~condvar ~condvar (or ~@(rest xs)))))))))",
-        repl_env.clone()
-       ).expect("could not define macro or");
     repl_env
 }
 
 fn eval_fn(args: &mut Vec<MalType>, repl_env: Option<Env>) -> MalResult {
+    if args.len() != 1 {
+        return Err(MalError::WrongArguments(format!(
+            "Expected (eval form) but got {} arguments",
+            args.len()
+        )));
+    }
     eval(args.remove(0), repl_env.unwrap())
 }
 
 fn rep<S: Into<String>>(input: S, repl_env: Env) -> Result<String, MalError> {
     let out = read(input.into())?;
-    let out = eval(out, repl_env)?;
-    let out = print(out);
+    let out = eval(out, repl_env.clone())?;
+    let out = print(out, &repl_env);
     Ok(out)
 }
 
@@ -157,14 +199,21 @@ fn eval_list(ast: MalType, repl_env: Env) -> TailPositionResult {
             }) = first.lambda_val()
             {
                 call_lambda(env.clone(), args.clone(), body.clone(), vec)
+            } else if let Some(result) = call_data(&first, &vec) {
+                result.map(TailPosition::Return)
             } else {
                 Err(MalError::NotAFunction(first.clone()))
             }
         } else {
-            panic!("Eval'd list is empty!")
+            Err(MalError::WrongArguments(
+                "Cannot call an empty list".to_string(),
+            ))
         }
     } else {
-        panic!("Eval'd list is no longer a list!")
+        Err(MalError::WrongArguments(format!(
+            "Expected a list to call but got: {:?}",
+            new_ast
+        )))
     }
 }
 
@@ -201,20 +250,31 @@ fn eval_ast(ast: MalType, repl_env: Env) -> MalResult {
             );
             Ok(map)
         }
+        _MalType::Set(ref set, ref meta) => {
+            let mut new_set = BTreeSet::new();
+            for item in set {
+                new_set.insert(eval(item.clone(), repl_env.clone())?);
+            }
+            Ok(MalType::set_with_meta(new_set, meta.clone()))
+        }
         _ => Ok(ast.clone()),
     }
 }
 
-fn print(ast: MalType) -> String {
-    pr_str(&ast, true)
+/// Results are pretty printed when `*print-pretty*` is set to a truthy value.
+/// Either way they are limited by `*print-length*` and `*print-level*`.
+fn print(ast: MalType, repl_env: &Env) -> String {
+    match repl_env.get("*print-pretty*") {
+        Ok(ref pretty) if !pretty.is_falsey() => pretty_str(
+            &ast,
+            &PrettyOptions::default().print(PrintOptions::from_env(repl_env, true)),
+        ),
+        _ => pr_str_with(&ast, &PrintOptions::from_env(repl_env, true)),
+    }
 }
 
 fn list_len(list: &MalType) -> usize {
-    if let Some(vec) = list.list_or_vector_val() {
-        vec.len()
-    } else {
-        panic!("Expected a list but got: {:?}", list)
-    }
+    list.list_or_vector_val().map_or(0, Vec::len)
 }
 
 fn call_lambda(
@@ -231,10 +291,13 @@ fn call_lambda(
 fn process_special_form(ast: &mut MalType, repl_env: Env) -> Option<TailPositionResult> {
     if let Some(vec) = ast.list_val() {
         if let Some(special) = vec[0].symbol_val() {
+            let form = ast.clone();
             let mut vec = vec.iter().skip(1).cloned().collect();
             let result = match special {
-                "def!" => special_def(&mut vec, repl_env),
-                "defmacro!" => special_defmacro(&mut vec, repl_env),
+                "def!" => special_def(&mut vec, repl_env, form),
+                "def-dynamic!" => special_def_dynamic(&mut vec, repl_env, form),
+                "binding" => special_binding(&mut vec, repl_env),
+                "defmacro!" => special_defmacro(&mut vec, repl_env, form),
                 "macroexpand" => special_macroexpand(&mut vec, repl_env),
                 "let*" => special_let(&mut vec, repl_env),
                 "do" => special_do(&mut vec, repl_env),
@@ -251,26 +314,115 @@ fn process_special_form(ast: &mut MalType, repl_env: Env) -> Option<TailPosition
     None
 }
 
-fn special_def(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+/// Checks that a special form got between `min` and `max` arguments, naming
+/// the form and the shape it expects otherwise.
+fn check_args(
+    name: &str,
+    args: &[MalType],
+    min: usize,
+    max: usize,
+    shape: &str,
+) -> Result<(), MalError> {
+    if args.len() >= min && args.len() <= max {
+        Ok(())
+    } else {
+        let mut form = vec![MalType::symbol(name)];
+        form.extend(args.iter().cloned());
+        Err(MalError::WrongArguments(format!(
+            "Expected {} but got: {:?}",
+            shape,
+            MalType::list(form)
+        )))
+    }
+}
+
+/// `(def! name docstring? value)`. The whole form is kept as the name's
+/// source.
+fn special_def(vec: &mut Vec<MalType>, repl_env: Env, source: MalType) -> TailPositionResult {
+    check_args("def!", vec, 2, 3, "(def! name docstring? value)")?;
+    if let Some(name) = dynamic_name(&vec[0]) {
+        vec[0] = name;
+        return special_def_dynamic(vec, repl_env, source);
+    }
+    define("def!", vec, repl_env, false, source)
+}
+
+/// `(def-dynamic! name docstring? value)` defines a var that `binding` can
+/// rebind. `(def! ^:dynamic name value)` does the same.
+fn special_def_dynamic(
+    vec: &mut Vec<MalType>,
+    repl_env: Env,
+    source: MalType,
+) -> TailPositionResult {
+    check_args("def-dynamic!", vec, 2, 3, "(def-dynamic! name docstring? value)")?;
+    define("def-dynamic!", vec, repl_env, true, source)
+}
+
+/// The symbol in a name read as `^:dynamic name` or
+/// `^{:dynamic true} name`, which is `(with-meta name meta)`.
+fn dynamic_name(name: &MalType) -> Option<MalType> {
+    let form = name.list_val()?;
+    if form.len() != 3 || !is_symbol_named(&form[0], "with-meta") || !form[1].is_symbol() {
+        return None;
+    }
+    let dynamic = MalType::keyword("dynamic");
+    let is_dynamic = match form[2].hashmap_val() {
+        Some(meta) => meta.get(&dynamic).map(|val| !val.is_falsey()) == Some(true),
+        None => form[2] == dynamic,
+    };
+    if is_dynamic {
+        Some(form[1].clone())
+    } else {
+        None
+    }
+}
+
+fn define(
+    form: &str,
+    vec: &mut Vec<MalType>,
+    repl_env: Env,
+    dynamic: bool,
+    source: MalType,
+) -> TailPositionResult {
     let name = vec.remove(0);
+    let doc = docstring(form, vec)?;
     if let Some(sym) = name.symbol_val() {
-        let val = eval(vec.remove(0), repl_env.clone())?;
-        repl_env.set(sym, val.clone());
+        let mut val = eval(vec.remove(0), repl_env.clone())?.named(sym);
+        if let Some(doc) = doc {
+            val = with_doc(val, doc)?;
+        }
+        if dynamic {
+            repl_env.set_dynamic(sym, val.clone());
+        } else {
+            repl_env.set(sym, val.clone());
+        }
+        repl_env.set_source(sym, source);
         Ok(TailPosition::Return(val))
     } else {
         Err(MalError::WrongArguments(format!(
-            "Expected a symbol as the first argument to def! but got: {:?}",
-            name
+            "Expected a symbol as the first argument to {} but got: {:?}",
+            form, name
         )))
     }
 }
 
-fn special_defmacro(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+fn special_defmacro(vec: &mut Vec<MalType>, repl_env: Env, source: MalType) -> TailPositionResult {
+    check_args(
+        "defmacro!",
+        vec,
+        2,
+        3,
+        "(defmacro! name docstring? (fn* [params] body))",
+    )?;
     let name = vec.remove(0);
+    let doc = docstring("defmacro!", vec)?;
     if let Some(sym) = name.symbol_val() {
         let mut val = eval(vec.remove(0), repl_env.clone())?;
         if val.is_lambda() {
-            val = val.make_macro();
+            val = val.make_macro().named(sym);
+            if let Some(doc) = doc {
+                val = with_doc(val, doc)?;
+            }
         } else {
             return Err(MalError::WrongArguments(format!(
                 "Expected a fn as the second argument to defmacro! but got: {:?}",
@@ -278,6 +430,7 @@ fn special_defmacro(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult
             )));
         }
         repl_env.set(sym, val.clone());
+        repl_env.set_source(sym, source);
         Ok(TailPosition::Return(val))
     } else {
         Err(MalError::WrongArguments(format!(
@@ -288,30 +441,78 @@ fn special_defmacro(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult
 }
 
 fn special_macroexpand(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+    check_args("macroexpand", vec, 1, 1, "(macroexpand form)")?;
     let ast = vec.remove(0);
     let result = macroexpand(ast, repl_env)?;
     Ok(TailPosition::Return(result))
 }
 
+/// `(binding [name value ...] body...)` gives dynamic vars new values while
+/// the body runs, including in functions it calls, and restores the old
+/// values afterwards, even when the body throws.
+fn special_binding(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+    check_args("binding", vec, 1, usize::MAX, "(binding [name value ...] body...)")?;
+    let bindings = vec.remove(0);
+    let pairs = match bindings.list_or_vector_val() {
+        Some(pairs) if pairs.len() % 2 == 0 => pairs,
+        _ => {
+            return Err(MalError::WrongArguments(format!(
+                "Expected name and value pairs in binding but got: {:?}",
+                bindings
+            )))
+        }
+    };
+    let mut new_values = vec![];
+    for pair in pairs.chunks(2) {
+        let name = match pair[0].symbol_val() {
+            Some(name) if repl_env.is_dynamic(name) => name,
+            _ => {
+                return Err(MalError::WrongArguments(format!(
+                    "Cannot bind {:?} because it is not a dynamic var declared with def-dynamic!",
+                    pair[0]
+                )))
+            }
+        };
+        new_values.push((name, eval(pair[1].clone(), repl_env.clone())?));
+    }
+    let mut old_values = vec![];
+    for (name, val) in new_values {
+        let env = repl_env.find(name).expect("dynamic var is bound");
+        old_values.push((env.clone(), name, env.get(name)?));
+        env.set(name, val);
+    }
+    let result = eval_body(vec.clone(), repl_env);
+    for (env, name, val) in old_values.into_iter().rev() {
+        env.set(name, val);
+    }
+    result.map(TailPosition::Return)
+}
+
 fn special_let(vec: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+    check_args("let*", vec, 2, 2, "(let* [name value ...] body)")?;
     let inner_repl_env = Env::new(Some(&repl_env));
     let bindings = vec.remove(0);
     if let Some(bindings) = bindings.list_or_vector_val() {
         if bindings.len() % 2 != 0 {
-            return Err(MalError::Parse(
-                "Odd number of let* binding values!".to_string(),
-            ));
+            return Err(MalError::WrongArguments(format!(
+                "Expected name and value pairs in let* bindings but got: {:?}",
+                bindings
+            )));
         }
         let mut bindings = bindings.clone();
         loop {
             if bindings.len() == 0 {
                 break;
             }
-            if let Some(name) = bindings.remove(0).symbol_val() {
+            let name = bindings.remove(0);
+            if let Some(name) = name.symbol_val() {
                 let val = eval(bindings.remove(0), inner_repl_env.clone())?;
                 inner_repl_env.set(name, val);
             } else {
-                return Err(MalError::Parse("Expected symbol".to_string()));
+                return Err(MalError::WrongArguments(format!(
+                    "Expected a symbol to bind in let* but got: {:?}",
+                    name
+                )));
             }
         }
         let rest = vec.remove(0);
@@ -336,6 +537,7 @@ fn special_do(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
 }
 
 fn special_if(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+    check_args("if", list, 2, 3, "(if test then else?)")?;
     let condition = list[0].clone();
     let result = eval(condition, repl_env)?;
     if result.is_falsey() {
@@ -350,16 +552,24 @@ fn special_if(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
 }
 
 fn special_fn(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
-    let args = &list[0];
+    check_args("fn*", list, 2, 3, "(fn* [params] docstring? body)")?;
+    let args = list.remove(0);
+    let doc = docstring("fn*", list)?;
     if let Some(args) = args.list_or_vector_val() {
-        let mut args = args.clone();
-        let body = list[1].clone();
-        Ok(TailPosition::Return(MalType::lambda(Lambda {
+        check_params(args)?;
+        let args = args.clone();
+        let body = list.remove(0);
+        let lambda = MalType::lambda(Lambda {
             env: repl_env.clone(),
             args,
             body: vec![body],
             is_macro: false,
-        })))
+            name: None,
+        });
+        match doc {
+            Some(doc) => Ok(TailPosition::Return(with_doc(lambda, doc)?)),
+            None => Ok(TailPosition::Return(lambda)),
+        }
     } else {
         Err(MalError::WrongArguments(format!(
             "Expected a vector as the first argument to fn* but got: {:?}",
@@ -368,63 +578,202 @@ fn special_fn(list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
     }
 }
 
+/// Takes the docstring from the front of the remaining arguments of a form,
+/// when there is one before its last argument.
+fn docstring(name: &str, args: &mut Vec<MalType>) -> Result<Option<MalType>, MalError> {
+    if args.len() < 2 {
+        return Ok(None);
+    }
+    let doc = args.remove(0);
+    if doc.is_string() {
+        Ok(Some(doc))
+    } else {
+        Err(MalError::WrongArguments(format!(
+            "Expected a docstring in {} but got: {:?}",
+            name, doc
+        )))
+    }
+}
+
+/// Adds `:doc` to the metadata of a function or collection.
+fn with_doc(mut val: MalType, doc: MalType) -> MalResult {
+    let mut meta = match val.get_metadata() {
+        Some(meta) if meta.is_nil() => BTreeMap::new(),
+        Some(meta) if meta.is_hashmap() => meta.hashmap_val().unwrap().clone(),
+        _ => {
+            return Err(MalError::WrongArguments(format!(
+                "Cannot attach a docstring to {:?}",
+                val
+            )))
+        }
+    };
+    meta.insert(MalType::keyword("doc"), doc);
+    Ok(val.clone_with_meta(MalType::hashmap(meta)))
+}
+
+/// Parameters must be symbols, with at most one `&` followed by exactly one
+/// symbol for the rest.
+fn check_params(params: &[MalType]) -> Result<(), MalError> {
+    for (index, param) in params.iter().enumerate() {
+        match param.symbol_val() {
+            Some("&") if index + 2 != params.len() => {
+                return Err(MalError::WrongArguments(format!(
+                    "Expected exactly one parameter after & in fn* but got: {:?}",
+                    MalType::vector(params.to_vec())
+                )))
+            }
+            Some(_) => {}
+            None => {
+                return Err(MalError::WrongArguments(format!(
+                    "Expected fn* parameters to be symbols but got: {:?}",
+                    param
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
 fn special_quote(list: &mut Vec<MalType>, _repl_env: Env) -> TailPositionResult {
+    check_args("quote", list, 1, 1, "(quote form)")?;
     Ok(TailPosition::Return(list.remove(0)))
 }
 
 fn special_quasiquote(arg_list: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+    check_args("quasiquote", arg_list, 1, 1, "(quasiquote form)")?;
     Ok(TailPosition::Call(
-        quasiquote(arg_list, repl_env.clone()),
+        quasiquote(arg_list, repl_env.clone())?,
         None,
     ))
 }
 
-fn quasiquote(arg_list: &mut Vec<MalType>, repl_env: Env) -> MalType {
+fn quasiquote(arg_list: &mut Vec<MalType>, repl_env: Env) -> MalResult {
     if arg_list.len() == 0 {
-        return MalType::list(vec![]);
+        return Ok(MalType::list(vec![]));
     }
     let ast = arg_list.remove(0);
     if !is_pair(&ast) {
         let list = vec![MalType::symbol("quote"), ast];
-        MalType::list(list)
-    } else if is_symbol_named(&car(&ast), "unquote") {
-        car(&cdr(&ast))
-    } else if is_pair(&car(&ast)) && is_symbol_named(&car(&car(&ast)), "splice-unquote") {
+        Ok(MalType::list(list))
+    } else if is_symbol_named(&car(&ast)?, "unquote") {
+        car(&cdr(&ast)?).map_err(|_| {
+            MalError::WrongArguments(format!("Expected (unquote form) but got: {:?}", ast))
+        })
+    } else if is_pair(&car(&ast)?) && is_symbol_named(&car(&car(&ast)?)?, "splice-unquote") {
+        let splice = car(&ast)?;
         let list = vec![
             MalType::symbol("concat"),
-            car(&cdr(&car(&ast))),
-            quasiquote(&mut vec![cdr(&ast)], repl_env),
+            car(&cdr(&splice)?).map_err(|_| {
+                MalError::WrongArguments(format!(
+                    "Expected (splice-unquote form) but got: {:?}",
+                    splice
+                ))
+            })?,
+            quasiquote(&mut vec![cdr(&ast)?], repl_env)?,
         ];
-        MalType::list(list)
+        Ok(MalType::list(list))
     } else {
-        let mut first = vec![car(&ast)];
-        let mut rest = vec![cdr(&ast)];
+        let mut first = vec![car(&ast)?];
+        let mut rest = vec![cdr(&ast)?];
         let list = vec![
             MalType::symbol("cons"),
-            quasiquote(&mut first, repl_env.clone()),
-            quasiquote(&mut rest, repl_env),
+            quasiquote(&mut first, repl_env.clone())?,
+            quasiquote(&mut rest, repl_env)?,
         ];
-        MalType::list(list)
+        Ok(MalType::list(list))
     }
 }
 
+/// `(try* expr clauses...)` where each clause is one of
+///
+/// * `(catch* e body...)`, which catches anything and binds a built-in error
+///   as its message string;
+/// * `(catch* :type e body...)`, which catches errors whose `:type` matches,
+///   or anything for `:default`, and binds built-in errors as `ex-info`;
+/// * `(finally* body...)`, which runs last whether or not there was an error.
+///
+/// `exit` is never caught, though `finally*` still runs.
 fn special_try_catch(args: &mut Vec<MalType>, repl_env: Env) -> TailPositionResult {
+    if args.is_empty() {
+        return Err(MalError::WrongArguments(
+            "try* requires an expression".to_string(),
+        ));
+    }
     let expr = args.remove(0);
-    let mut catch = vec_result(&args.remove(0))?;
-    catch.remove(0); // catch* symbol not needed
-    let error_name = catch.remove(0);
-    let catch_expr = catch.remove(0);
-    match eval(expr, repl_env.clone()) {
-        Ok(result) => Ok(TailPosition::Return(result)),
+    let mut catches = vec![];
+    let mut finally = None;
+    for clause in args.iter() {
+        let parts = clause.list_val().cloned().unwrap_or_else(Vec::new);
+        if !parts.is_empty() && is_symbol_named(&parts[0], "catch*") {
+            let typed = parts.len() > 1 && parts[1].is_keyword();
+            let name_index = if typed { 2 } else { 1 };
+            match parts.get(name_index) {
+                Some(name) if name.is_symbol() && finally.is_none() => catches.push((
+                    if typed { Some(parts[1].clone()) } else { None },
+                    name.clone(),
+                    parts[name_index + 1..].to_vec(),
+                )),
+                _ => {
+                    return Err(MalError::WrongArguments(format!(
+                        "Expected (catch* e body...) or (catch* :type e body...) before any \
+                         finally* but got: {:?}",
+                        clause
+                    )))
+                }
+            }
+        } else if !parts.is_empty() && is_symbol_named(&parts[0], "finally*") {
+            if finally.is_some() {
+                return Err(MalError::WrongArguments(format!(
+                    "Expected at most one finally* clause in try* but got another: {:?}",
+                    clause
+                )));
+            }
+            finally = Some(parts[1..].to_vec());
+        } else {
+            return Err(MalError::WrongArguments(format!(
+                "Expected a catch* or finally* clause in try* but got: {:?}",
+                clause
+            )));
+        }
+    }
+    let result = match eval(expr, repl_env.clone()) {
+        Err(MalError::Exit(status)) => Err(MalError::Exit(status)),
         Err(err) => {
-            let err_type = match err {
-                MalError::Generic(err_val) => err_val,
-                _ => MalType::string(err.to_string()),
-            };
-            let inner_env = Env::with_binds(Some(&repl_env), vec![error_name], vec![err_type]);
-            Ok(TailPosition::Return(eval(catch_expr, inner_env)?))
+            let err_type = err.type_keyword();
+            let clause = catches.into_iter().find(|&(ref catch_type, _, _)| match *catch_type {
+                Some(ref catch_type) => {
+                    *catch_type == err_type || catch_type.keyword_val() == Some("default")
+                }
+                None => true,
+            });
+            match clause {
+                Some((catch_type, name, body)) => {
+                    let value = match (catch_type, err) {
+                        (_, MalError::Generic(val)) => val,
+                        (None, err) => MalType::string(err.to_string()),
+                        (Some(_), err) => err.to_value(),
+                    };
+                    let inner_env = Env::with_binds(Some(&repl_env), vec![name], vec![value]);
+                    eval_body(body, inner_env)
+                }
+                None => Err(err),
+            }
         }
+        result => result,
+    };
+    if let Some(body) = finally {
+        eval_body(body, repl_env)?;
     }
+    result.map(TailPosition::Return)
+}
+
+/// Evaluates each form in turn, returning the last value or nil.
+fn eval_body(body: Vec<MalType>, repl_env: Env) -> MalResult {
+    let mut result = MalType::nil();
+    for form in body {
+        result = eval(form, repl_env.clone())?;
+    }
+    Ok(result)
 }
 
 fn is_symbol_named(val: &MalType, name: &str) -> bool {
@@ -442,25 +791,29 @@ fn is_pair(arg: &MalType) -> bool {
     }
 }
 
-fn car(arg: &MalType) -> MalType {
-    if let Some(vec) = arg.list_or_vector_val() {
-        vec[0].clone()
-    } else {
-        panic!("Expected a list to car but got: {:?}", arg)
+fn car(arg: &MalType) -> MalResult {
+    match arg.list_or_vector_val() {
+        Some(vec) if !vec.is_empty() => Ok(vec[0].clone()),
+        _ => Err(MalError::WrongArguments(format!(
+            "Expected a non-empty list but got: {:?}",
+            arg
+        ))),
     }
 }
 
-fn cdr(arg: &MalType) -> MalType {
-    if let Some(vec) = arg.list_or_vector_val() {
-        MalType::list(vec[1..].to_owned())
-    } else {
-        panic!("Expected a list to cdr but got: {:?}", arg)
+fn cdr(arg: &MalType) -> MalResult {
+    match arg.list_or_vector_val() {
+        Some(vec) if !vec.is_empty() => Ok(MalType::list(vec[1..].to_owned())),
+        _ => Err(MalError::WrongArguments(format!(
+            "Expected a non-empty list but got: {:?}",
+            arg
+        ))),
     }
 }
 
 fn is_macro_call(ast: &MalType, env: Env) -> bool {
-    if is_pair(ast) {
-        if let Some(sym) = car(ast).symbol_val() {
+    if let Ok(head) = car(ast) {
+        if let Some(sym) = head.symbol_val() {
             if let Ok(val) = env.get(sym) {
                 if let Some(Lambda { is_macro, .. }) = val.lambda_val() {
                     return *is_macro;
@@ -473,13 +826,14 @@ fn is_macro_call(ast: &MalType, env: Env) -> bool {
 
 fn macroexpand(mut ast: MalType, env: Env) -> MalResult {
     while is_macro_call(&ast, env.clone()) {
-        if let Some(sym) = car(&ast).symbol_val() {
+        let head = car(&ast)?;
+        if let Some(sym) = head.symbol_val() {
             let lambda = env.get(sym)?;
             if let Some(Lambda {
                 env, args, body, ..
             }) = lambda.lambda_val()
             {
-                let rest = vec_result(&cdr(&ast))?;
+                let rest = vec_result(&cdr(&ast)?)?;
                 let env = Env::with_binds(Some(&env), args.clone(), rest);
                 let expr = body.clone().remove(0);
                 ast = eval(expr, env)?;
@@ -487,7 +841,7 @@ fn macroexpand(mut ast: MalType, env: Env) -> MalResult {
                 return Err(MalError::NotAFunction(lambda.clone()));
             }
         } else {
-            panic!();
+            return Err(MalError::NotAFunction(head));
         }
     }
     Ok(ast)
@@ -496,6 +850,7 @@ fn macroexpand(mut ast: MalType, env: Env) -> MalResult {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use mal_rust::output::{self, Buffer};
 
     #[test]
     fn test_metadata() {
@@ -503,4 +858,512 @@ mod tests {
         let result = rep("(meta (with-meta (fn* (a) a) \"abc\"))", repl_env.clone()).unwrap();
         assert_eq!("\"abc\"", result);
     }
+
+    #[test]
+    fn test_chars() {
+        let repl_env = top_repl_env();
+        let result = rep("(map int (seq \"aé\"))", repl_env.clone()).unwrap();
+        assert_eq!("(97 233)", result);
+        let result = rep(
+            "(list (char 955) (char? \\a) (< \\a \\b) (str \\a \\b))",
+            repl_env.clone(),
+        ).unwrap();
+        assert_eq!("(\\λ true true \"ab\")", result);
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let repl_env = top_repl_env();
+        let cases = vec![
+            ("(subs \"héllo\" 1 3)", "\"él\""),
+            ("(subs \"héllo\" 2)", "\"llo\""),
+            ("(split \"a,b,,c\" \",\")", "[\"a\" \"b\" \"\" \"c\"]"),
+            ("(join \", \" [1 \"b\" :c])", "\"1, b, :c\""),
+            ("(upper-case \"straße\")", "\"STRASSE\""),
+            ("(trim \"  x \")", "\"x\""),
+            ("(replace \"a-b-c\" \"-\" \"+\")", "\"a+b+c\""),
+            ("(list (starts-with? \"abc\" \"ab\") (ends-with? \"abc\" \"b\"))", "(true false)"),
+            ("(list (index-of \"éaé\" \"é\" 1) (index-of \"abc\" \"z\"))", "(2 nil)"),
+            ("(list (includes? \"abc\" \"bc\") (blank? \" \\n\") (blank? nil))", "(true true true)"),
+            ("(format \"%-4s|%5d|%05d|%x|%c|%%\" \"ab\" 42 -42 255 \\z)", "\"ab  |   42|-0042|ff|z|%\""),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(expected, rep(code, repl_env.clone()).unwrap(), "{}", code);
+        }
+        assert_eq!(
+            MalError::WrongArguments(
+                "`upper-case` expected string at argument 1, got 1".to_string()
+            ),
+            rep("(upper-case 1)", repl_env.clone()).unwrap_err()
+        );
+        assert!(rep("(subs \"abc\" 4)", repl_env.clone()).is_err());
+        assert_eq!(
+            Err(MalError::WrongArguments(
+                "String index must not be negative but got: -2".to_string()
+            )),
+            rep("(subs \"abc\" -2)", repl_env.clone())
+        );
+        assert!(rep("(format \"%d\")", repl_env.clone()).is_err());
+        assert!(rep("(format \"%99999999999999999999999d\" 1)", repl_env.clone()).is_err());
+        assert!(rep("(format \"%.10001s\" 1)", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_regex_functions() {
+        let repl_env = top_repl_env();
+        let cases = vec![
+            ("#\"\\d+\"", "#\"\\d+\""),
+            ("(re-find #\"\\d+\" \"ab 12 34\")", "\"12\""),
+            ("(re-find #\"(\\w)(\\d)?\" \"a\")", "[\"a\" \"a\" nil]"),
+            ("(list (re-matches #\"a|ab\" \"ab\") (re-matches #\"a\" \"ab\"))", "(\"ab\" nil)"),
+            ("(re-seq #\"\\d\" \"1a2b3\")", "(\"1\" \"2\" \"3\")"),
+            ("(re-groups #\"\\d\" \"x5\")", "[\"5\"]"),
+            ("(re-find (re-pattern \"é+\") \"céé\")", "\"éé\""),
+            ("(replace \"a1b22\" #\"(\\d+)\" \"<$1>\")", "\"a<1>b<22>\""),
+            ("(replace \"a1b2\" #\"\\d\" (fn* (d) (str d d)))", "\"a11b22\""),
+            ("(split \"a, b,c\" #\",\\s*\")", "[\"a\" \"b\" \"c\"]"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(expected, rep(code, repl_env.clone()).unwrap(), "{}", code);
+        }
+        assert!(rep("(re-pattern \"(\")", repl_env.clone()).is_err());
+        assert!(rep("(re-find \"a\" \"a\")", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_pretty_print_mode() {
+        let repl_env = top_repl_env();
+        let code = "(def! x (apply vector \
+                    (map (fn* [n] {:id n :name (str \"item-\" n)}) (list 1 2 3 4))))";
+        let result = rep(code, repl_env.clone()).unwrap();
+        assert!(!result.contains('\n'));
+        rep("(def! *print-pretty* true)", repl_env.clone()).unwrap();
+        let result = rep("x", repl_env.clone()).unwrap();
+        assert_eq!(
+            "[{:id 1 :name \"item-1\"}\n \
+             {:id 2 :name \"item-2\"}\n \
+             {:id 3 :name \"item-3\"}\n \
+             {:id 4 :name \"item-4\"}]",
+            result
+        );
+        rep("(def! *print-length* 2)", repl_env.clone()).unwrap();
+        assert_eq!("[1 2 ...]", rep("[1 2 3 4]", repl_env.clone()).unwrap());
+    }
+
+    #[test]
+    fn test_print_limits() {
+        let repl_env = top_repl_env();
+        rep("(def! a (atom nil))", repl_env.clone()).unwrap();
+        rep("(reset! a {:self a})", repl_env.clone()).unwrap();
+        assert_eq!("(atom {:self #<cycle>})", rep("a", repl_env.clone()).unwrap());
+        rep("(def! *print-length* 2)", repl_env.clone()).unwrap();
+        rep("(def! *print-level* 1)", repl_env.clone()).unwrap();
+        assert_eq!(
+            "\"(1 # ...)\"",
+            rep("(pr-str (list 1 [2] 3))", repl_env.clone()).unwrap()
+        );
+        assert_eq!("(1 2 ...)", rep("(list 1 2 3)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "\"[1 # ...]\\n\"",
+            rep("(with-out-str (pprint [1 [2] 3]))", repl_env.clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sets_and_edn() {
+        let repl_env = top_repl_env();
+        assert_eq!("#{2 3}", rep("#{(+ 1 1) 3}", repl_env.clone()).unwrap());
+        assert_eq!("#{1}", rep("(disj (hash-set 1 2 2) 2)", repl_env.clone()).unwrap());
+        rep(
+            "(def! *data-readers* (hash-map 'point (fn* [p] {:x (nth p 0) :y (nth p 1)})))",
+            repl_env.clone(),
+        ).unwrap();
+        assert_eq!(
+            "[{:x 1 :y 2} 0]",
+            rep(
+                "(read-edn \"[#point [1 2] #_ skipped #inst \\\"1970\\\"]\")",
+                repl_env.clone()
+            ).unwrap()
+        );
+        assert!(rep("(read-edn \"#nope 1\")", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_data_as_functions() {
+        let repl_env = top_repl_env();
+        rep("(def! user {:name \"Tim\" :langs [:rust :ruby]})", repl_env.clone()).unwrap();
+        assert_eq!("\"Tim\"", rep("(:name user)", repl_env.clone()).unwrap());
+        assert_eq!(":none", rep("(:age user :none)", repl_env.clone()).unwrap());
+        assert_eq!(":ruby", rep("((:langs user) 1)", repl_env.clone()).unwrap());
+        assert_eq!("0", rep("([] 5 0)", repl_env.clone()).unwrap());
+        assert_eq!("2", rep("(#{1 2} 2)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "(\"Tim\" nil)",
+            rep("(map :name [user {}])", repl_env.clone()).unwrap()
+        );
+        assert_eq!("1", rep("(apply {:a 1} [:a])", repl_env.clone()).unwrap());
+        assert!(rep("([1] 3)", repl_env.clone()).is_err());
+        assert_eq!(
+            Err(MalError::WrongArguments(
+                "Vector index must not be negative but got: -1".to_string()
+            )),
+            rep("([1] -1)", repl_env.clone())
+        );
+        assert_eq!("0", rep("([1] -1 0)", repl_env.clone()).unwrap());
+        assert!(rep("(:a)", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_map_functions() {
+        let repl_env = top_repl_env();
+        rep("(def! cfg {:db {:hosts [\"a\" \"b\"] :port 5432}})", repl_env.clone()).unwrap();
+        assert_eq!("\"b\"", rep("(get-in cfg [:db :hosts 1])", repl_env.clone()).unwrap());
+        assert_eq!(":none", rep("(get-in cfg [:web :port] :none)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "{:db {:hosts [\"a\" \"c\"] :port 5433}}",
+            rep(
+                "(update-in (assoc-in cfg [:db :hosts 1] \"c\") [:db :port] + 1)",
+                repl_env.clone()
+            ).unwrap()
+        );
+        assert_eq!("{:a {:b 1}}", rep("(assoc-in nil [:a :b] 1)", repl_env.clone()).unwrap());
+        assert_eq!("[1 3]", rep("(update [1 2] 1 + 1)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "{:a 3 :b 2}",
+            rep("(merge-with + {:a 1} nil {:a 2 :b 2})", repl_env.clone()).unwrap()
+        );
+        assert_eq!("{:a 1}", rep("(select-keys {:a 1 :b 2} [:a :c])", repl_env.clone()).unwrap());
+        assert_eq!("{:a 1 :b 2}", rep("(zipmap [:a :b :c] [1 2])", repl_env.clone()).unwrap());
+        assert_eq!("{:a 1 :b 2}", rep("(into {:a 1} [[:b 2]])", repl_env.clone()).unwrap());
+        assert_eq!("(3 2 1)", rep("(into () [1 2 3])", repl_env.clone()).unwrap());
+        assert_eq!("(2 1 0)", rep("(into (list 0) [1 2])", repl_env.clone()).unwrap());
+        assert_eq!(
+            "4",
+            rep("(reduce-kv (fn* [acc k v] (+ acc k v)) 0 [1 2])", repl_env.clone()).unwrap()
+        );
+        assert_eq!(
+            MalError::WrongArguments(
+                "get-in cannot use path element 3 (:x) on 5432".to_string()
+            ),
+            rep("(get-in cfg [:db :port :x])", repl_env.clone()).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_sequence_functions() {
+        let repl_env = top_repl_env();
+        let cases = [
+            ("(filter (fn* [x] (> x 1)) [1 2 3])", "(2 3)"),
+            ("(remove (fn* [x] (> x 1)) [1 2 3])", "(1)"),
+            ("(reduce + [1 2 3])", "6"),
+            ("(reduce + 10 [])", "10"),
+            ("(reduce + [5])", "5"),
+            ("(some (fn* [x] (if (> x 1) (* x 10))) [1 2 3])", "20"),
+            ("(every? number? [1 :a])", "false"),
+            ("(take-while (fn* [x] (< x 3)) [1 2 3 1])", "(1 2)"),
+            ("(drop-while (fn* [x] (< x 3)) [1 2 3 1])", "(3 1)"),
+            ("(partition 2 [1 2 3 4 5])", "((1 2) (3 4))"),
+            ("(partition 2 1 [:pad] [1 2 3])", "((1 2) (2 3) (3 :pad))"),
+            ("(interleave [1 2 3] [:a :b])", "(1 :a 2 :b)"),
+            ("(distinct [3 1 3 2 1])", "(3 1 2)"),
+            ("(frequencies [:a :b :a])", "{:a 2 :b 1}"),
+            ("(group-by first [[1 :a] [2 :b] [1 :c]])", "{1 [[1 :a] [1 :c]] 2 [[2 :b]]}"),
+            ("(sort [10 9 100 -1])", "(-1 9 10 100)"),
+            ("(sort > [1 3 2])", "(3 2 1)"),
+            ("(sort-by count [[1 2] [] [3]])", "([] [3] [1 2])"),
+            ("(reverse [1 2 3])", "(3 2 1)"),
+            ("(mapcat (fn* [x] [x x]) [1 2])", "(1 1 2 2)"),
+            ("(map + [1 2 3] [10 20])", "(11 22)"),
+        ];
+        for &(code, expected) in cases.iter() {
+            assert_eq!(expected, rep(code, repl_env.clone()).unwrap(), "{}", code);
+        }
+        let numbers: Vec<String> = (0..100_000).map(|n| n.to_string()).collect();
+        let code = format!("(reduce + (filter number? [{}]))", numbers.join(" "));
+        assert_eq!("4999950000", rep(&code, repl_env.clone()).unwrap());
+        assert!(rep("(sort [1 :a])", repl_env.clone()).is_err());
+        let scrambled: Vec<String> = (0..30).map(|n| (n * 7919 % 1000).to_string()).collect();
+        let code = format!(
+            "(count (sort (fn* [a b] (if (< a 500) true (> a b))) [{}]))",
+            scrambled.join(" ")
+        );
+        assert_eq!("30", rep(&code, repl_env.clone()).unwrap());
+        let code = format!(
+            "(sort (fn* [a b] (if (= a 7) (throw :bad) (< a b))) [7 {}])",
+            scrambled.join(" ")
+        );
+        assert!(rep(&code, repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_stdlib() {
+        let repl_env = top_repl_env();
+        assert_eq!("4", rep("(-> 1 inc (* 2))", repl_env.clone()).unwrap());
+        assert_eq!(
+            "(3 4)",
+            rep("(->> [1 2 3] (map inc) (filter (fn* [x] (> x 2))))", repl_env.clone()).unwrap()
+        );
+        assert_eq!("nil", rep("(when false 1)", repl_env.clone()).unwrap());
+        assert_eq!("2", rep("(if-let [x (get {:a 1} :a)] (inc x) 0)", repl_env.clone()).unwrap());
+        assert_eq!("0", rep("(if-let [x (get {:a 1} :b)] (inc x) 0)", repl_env.clone()).unwrap());
+        assert_eq!("3", rep("(when-let [x 2] (inc x))", repl_env.clone()).unwrap());
+        rep("(def! total (atom 0))", repl_env.clone()).unwrap();
+        rep("(doseq [x [1 2 3]] (swap! total + x))", repl_env.clone()).unwrap();
+        rep("(dotimes [i 10000] (swap! total + i))", repl_env.clone()).unwrap();
+        assert_eq!("49995006", rep("@total", repl_env.clone()).unwrap());
+        for code in &["(inc 1)", "(not 1)", "(cond true 1)", "(or 1)"] {
+            assert!(rep(*code, bare_repl_env()).is_err());
+        }
+        assert_eq!(
+            "\"cond requires an even number of forms\"",
+            rep("(try* (cond true) (catch* e e))", repl_env.clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_try_catch_finally() {
+        let repl_env = top_repl_env();
+        let code = "(try* (/ 1 0)
+                      (catch* :symbol-undefined e :undefined)
+                      (catch* :divide-by-zero e (:type (ex-data e))))";
+        assert_eq!(":divide-by-zero", rep(code, repl_env.clone()).unwrap());
+        assert_eq!(
+            "\"'nope' not found\"",
+            rep("(try* nope (catch* e e))", repl_env.clone()).unwrap()
+        );
+        assert_eq!(
+            "nope",
+            rep("(try* nope (catch* :default e (:symbol (ex-data e))))", repl_env.clone()).unwrap()
+        );
+        rep("(def! log (atom []))", repl_env.clone()).unwrap();
+        let code = "(try* (throw (ex-info \"bad input\" {:type :invalid :field :age}))
+                      (catch* :invalid e (do (swap! log conj (ex-message e)) (ex-data e)))
+                      (finally* (swap! log conj :done)))";
+        assert_eq!("{:field :age :type :invalid}", rep(code, repl_env.clone()).unwrap());
+        assert_eq!("[\"bad input\" :done]", rep("@log", repl_env.clone()).unwrap());
+        let err = rep(
+            "(try* (throw (ex-info \"x\" {})) (catch* :other e 1) (finally* (swap! log conj 1)))",
+            repl_env.clone(),
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "x {}");
+        assert_eq!("[\"bad input\" :done 1]", rep("@log", repl_env.clone()).unwrap());
+        assert_eq!(
+            "#<ex-info \"x\" {:a 1}>",
+            rep("(ex-info \"x\" {:a 1})", repl_env.clone()).unwrap()
+        );
+        assert_eq!("nil", rep("(ex-message {:message \"m\" :data {}})", repl_env.clone()).unwrap());
+        let code = "(try* (throw {:message \"m\" :data {:type :boom}})
+                      (catch* :boom e :boom)
+                      (catch* :error e :plain))";
+        assert_eq!(":plain", rep(code, repl_env.clone()).unwrap());
+        assert!(rep("(try* 1 (finally* 2) (finally* 3))", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_special_form_errors() {
+        let repl_env = top_repl_env();
+        assert_eq!(
+            rep("(def!)", repl_env.clone()).unwrap_err().to_string(),
+            "Wrong arguments: Expected (def! name docstring? value) but got: (def!)"
+        );
+        assert_eq!(
+            rep("(let* [a] a)", repl_env.clone()).unwrap_err().to_string(),
+            "Wrong arguments: Expected name and value pairs in let* bindings but got: [a]"
+        );
+        assert_eq!(
+            rep("(fn* [1] 1)", repl_env.clone()).unwrap_err().to_string(),
+            "Wrong arguments: Expected fn* parameters to be symbols but got: 1"
+        );
+        assert!(rep("(fn* [a &] 1)", repl_env.clone()).is_err());
+        assert!(rep("(if)", repl_env.clone()).is_err());
+        assert!(rep("(quote)", repl_env.clone()).is_err());
+        assert!(rep("`(unquote)", repl_env.clone()).is_err());
+        assert!(rep("(eval)", repl_env.clone()).is_err());
+        assert_eq!("1", rep("(if true 1)", repl_env.clone()).unwrap());
+    }
+
+    #[test]
+    fn test_builtin_signatures() {
+        let repl_env = top_repl_env();
+        let cases = vec![
+            ("(+ 1 :foo)", "`+` expected number at argument 2, got :foo"),
+            ("(count 1 2 3)", "`count` expected 1 argument, got 3"),
+            ("(map inc)", "`map` expected at least 2 arguments, got 1"),
+            ("(subs \"abc\")", "`subs` expected 2 to 3 arguments, got 1"),
+            ("(filter 1 [1])", "`filter` expected function at argument 1, got 1"),
+            ("(keyword 1)", "`keyword` expected string or keyword at argument 1, got 1"),
+            ("(get [1] 0)", "`get` expected map, set or nil at argument 1, got [1]"),
+            ("(char \"a\")", "`char` expected number or char at argument 1, got \"a\""),
+            ("(merge {} 1)", "`merge` expected map or nil at argument 2, got 1"),
+            ("(split \"a\" 1)", "`split` expected string or regex at argument 2, got 1"),
+            ("(get-in {} {})", "`get-in` expected list or vector at argument 2, got {}"),
+            ("(seq 1)", "`seq` expected collection or string at argument 1, got 1"),
+            ("(conj {} 1)", "`conj` expected [key value] pair at argument 2, got 1"),
+            ("(setenv \"A\" 1)", "`setenv` expected string at argument 2, got 1"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(
+                MalError::WrongArguments(expected.to_string()),
+                rep(code, repl_env.clone()).unwrap_err()
+            );
+        }
+        assert_eq!("2", rep("(count {:a 1 :b 2})", repl_env.clone()).unwrap());
+        assert_eq!("(1)", rep("(cons 1 nil)", repl_env.clone()).unwrap());
+        assert_eq!("(2 1)", rep("(conj nil 1 2)", repl_env.clone()).unwrap());
+        assert_eq!("{:a 1}", rep("(conj {} [:a 1])", repl_env.clone()).unwrap());
+        assert_eq!("([:a 1])", rep("(seq {:a 1})", repl_env.clone()).unwrap());
+        assert_eq!("false", rep("(contains? nil :a)", repl_env.clone()).unwrap());
+    }
+
+    #[test]
+    fn test_docstrings() {
+        let repl_env = top_repl_env();
+        rep("(def! square \"Returns n times n.\" (fn* [n] (* n n)))", repl_env.clone()).unwrap();
+        assert_eq!("9", rep("(square 3)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "{:doc \"Returns n times n.\"}",
+            rep("(meta square)", repl_env.clone()).unwrap()
+        );
+        assert_eq!(
+            "{:doc \"Adds.\"}",
+            rep("(meta (fn* [a b] \"Adds.\" (+ a b)))", repl_env.clone()).unwrap()
+        );
+        assert_eq!("\"hi\"", rep("((fn* [] \"hi\"))", repl_env.clone()).unwrap());
+        assert_eq!("(square)", rep("(apropos #\"^squ\")", repl_env.clone()).unwrap());
+        assert_eq!("nil", rep("(doc 'square)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "\"Evaluates body when test is truthy, and is otherwise nil.\"",
+            rep("(:doc (meta when))", repl_env.clone()).unwrap()
+        );
+        assert!(rep("(def! x \"Not a function.\" 1)", repl_env.clone()).is_err());
+        assert!(rep("(def! x 1 2)", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_lambda_introspection() {
+        let repl_env = top_repl_env();
+        rep("(def! add (fn* [a b] (+ a b)))", repl_env.clone()).unwrap();
+        assert_eq!("#<fn add [a b]>", rep("add", repl_env.clone()).unwrap());
+        assert_eq!("#<fn add [a b]>", rep("(do (def! plus add) plus)", repl_env.clone()).unwrap());
+        assert_eq!("#<fn [x]>", rep("(fn* [x] x)", repl_env.clone()).unwrap());
+        assert_eq!("#<macro when [test & body]>", rep("when", repl_env.clone()).unwrap());
+        assert_eq!("[a b]", rep("(fn-args add)", repl_env.clone()).unwrap());
+        assert_eq!("(+ a b)", rep("(fn-body add)", repl_env.clone()).unwrap());
+        assert_eq!("nil", rep("(fn-args +)", repl_env.clone()).unwrap());
+        assert!(rep("(fn-body 1)", repl_env.clone()).is_err());
+        assert_eq!(
+            "\"(def! add (fn* [a b] (+ a b)))\\n\"",
+            rep("(with-out-str (source 'add))", repl_env.clone()).unwrap()
+        );
+        rep("(def! five (+ 2 3))", repl_env.clone()).unwrap();
+        assert_eq!(
+            "\"(def! five (+ 2 3))\\n\"",
+            rep("(with-out-str (source 'five))", repl_env.clone()).unwrap()
+        );
+        assert_eq!(
+            "\"Source not found\\n\"",
+            rep("(with-out-str (source '+))", repl_env.clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_binding() {
+        let repl_env = top_repl_env();
+        rep("(def-dynamic! *depth* 0)", repl_env.clone()).unwrap();
+        rep("(def! depth (fn* [] *depth*))", repl_env.clone()).unwrap();
+        assert_eq!(
+            "(1 2)",
+            rep("(binding [*depth* 1] (list (depth) (binding [*depth* 2] (depth))))", repl_env.clone())
+                .unwrap()
+        );
+        assert_eq!("0", rep("(depth)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "0",
+            rep("(try* (binding [*depth* 9] (throw :oops)) (catch* e (depth)))", repl_env.clone())
+                .unwrap()
+        );
+        rep("(def! ^:dynamic *width* 80)", repl_env.clone()).unwrap();
+        assert_eq!("40", rep("(binding [*width* 40] *width*)", repl_env.clone()).unwrap());
+        assert_eq!(
+            "\"(1 ...)\"",
+            rep("(binding [*print-length* 1] (pr-str '(1 2)))", repl_env.clone()).unwrap()
+        );
+        rep("(def! lexical 1)", repl_env.clone()).unwrap();
+        assert!(rep("(binding [lexical 2] lexical)", repl_env.clone()).is_err());
+        assert!(rep("(let* [*depth* 5] (binding [*depth* 1] 1))", repl_env.clone()).is_err());
+    }
+
+    #[test]
+    fn test_output_redirection() {
+        let repl_env = top_repl_env();
+        assert_eq!(
+            "\"1 \\\"a\\\"\\nb\\n\"",
+            rep("(with-out-str (prn 1 \"a\") (println \"b\"))", repl_env.clone()).unwrap()
+        );
+        let buffer = Buffer::new();
+        output::set_stderr(Box::new(buffer.clone()));
+        rep("(binding [*out* *err*] (println \"warning\"))", repl_env.clone()).unwrap();
+        assert_eq!("warning\n", buffer.contents());
+    }
+
+    #[test]
+    fn test_with_open() {
+        let repl_env = top_repl_env();
+        let path = env::temp_dir().join(format!("mal-with-open-{}.txt", process::id()));
+        rep(format!("(def! path {:?})", path.to_string_lossy()), repl_env.clone()).unwrap();
+        rep(
+            "(with-open [out (open-writer path)] (binding [*out* out] (println \"a\") (prn :b)))",
+            repl_env.clone(),
+        ).unwrap();
+        assert_eq!(
+            "(\"a\" \":b\")",
+            rep("(with-open [in (open-reader path)] (line-seq in))", repl_env.clone()).unwrap()
+        );
+        rep("(def! f (open-reader path))", repl_env.clone()).unwrap();
+        assert!(rep("(with-open [in f] (throw :oops))", repl_env.clone()).is_err());
+        assert_eq!(
+            format!("\"IO Error: {}: file is closed\"", path.to_string_lossy()),
+            rep("(try* (read-line f) (catch* :io-error e (ex-message e)))", repl_env.clone()).unwrap()
+        );
+        rep("(delete-file path)", repl_env.clone()).unwrap();
+    }
+
+    #[test]
+    fn test_exit() {
+        let repl_env = top_repl_env();
+        rep("(def! cleaned (atom false))", repl_env.clone()).unwrap();
+        assert_eq!(
+            Err(MalError::Exit(3)),
+            rep("(try* (exit 3) (catch* e :caught) (finally* (reset! cleaned true)))", repl_env.clone())
+        );
+        assert_eq!("true", rep("@cleaned", repl_env.clone()).unwrap());
+        assert_eq!(
+            "\"hi\"",
+            rep("(:out (sh \"cat\" {:in \"hi\"}))", repl_env.clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_from_in() {
+        let repl_env = top_repl_env();
+        let path = env::temp_dir().join(format!("mal-in-{}.txt", process::id()));
+        rep(format!("(def! path {:?})", path.to_string_lossy()), repl_env.clone()).unwrap();
+        rep("(spit path \"one\\ntwo\\nthree\\n\")", repl_env.clone()).unwrap();
+        assert_eq!(
+            "[\"one\" (\"two\" \"three\")]",
+            rep(
+                "(with-open [in (open-reader path)] (binding [*in* in] [(read-line) (line-seq)]))",
+                repl_env.clone()
+            ).unwrap()
+        );
+        assert_eq!(
+            "[\"one\" \"two\\nthree\\n\"]",
+            rep(
+                "(with-open [in (open-reader path)] (binding [*in* in] [(read-line) (read-all-stdin)]))",
+                repl_env.clone()
+            ).unwrap()
+        );
+        assert!(rep("(binding [*in* 1] (read-line))", repl_env.clone()).is_err());
+        rep("(delete-file path)", repl_env.clone()).unwrap();
+    }
 }
@@ -0,0 +1,326 @@
+//! A compact binary encoding of mal data, and the `encode` and `decode`
+//! builtins.
+//!
+//! Values are written as MessagePack. Nil, booleans, numbers, strings,
+//! vectors and maps use the plain MessagePack types, so other MessagePack
+//! readers see ordinary data. Everything else is an extension type:
+//!
+//! | ext | value                                            |
+//! |-----|--------------------------------------------------|
+//! | 1   | keyword, as UTF-8                                |
+//! | 2   | symbol, as UTF-8                                 |
+//! | 3   | char, as UTF-8                                   |
+//! | 4   | list, holding an encoded array                   |
+//! | 5   | set, holding an encoded array                    |
+//! | 6   | value with metadata, holding the value then meta |
+
+use types::*;
+use env::Env;
+
+use rmp::decode;
+use rmp::encode;
+use rmp::Marker;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+const EXT_KEYWORD: i8 = 1;
+const EXT_SYMBOL: i8 = 2;
+const EXT_CHAR: i8 = 3;
+const EXT_LIST: i8 = 4;
+const EXT_SET: i8 = 5;
+const EXT_META: i8 = 6;
+
+/// How deeply decoded values may nest, so hostile input cannot overflow the
+/// stack. This is the same limit `serde_json` uses.
+const MAX_DEPTH: usize = 128;
+
+/// Encodes a value, failing on functions, atoms and regexes, which cannot
+/// be rebuilt from bytes.
+pub fn encode(value: &MalType) -> Result<Vec<u8>, MalError> {
+    let mut buf = vec![];
+    write_value(&mut buf, value)?;
+    Ok(buf)
+}
+
+/// Decodes a value written by `encode`. Trailing bytes are an error.
+pub fn decode(bytes: &[u8]) -> MalResult {
+    let mut rd = bytes;
+    let value = read_value(&mut rd, 0)?;
+    if rd.is_empty() {
+        Ok(value)
+    } else {
+        Err(invalid(format!("{} unexpected trailing bytes", rd.len())))
+    }
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &MalType) -> Result<(), MalError> {
+    match value.get_metadata() {
+        Some(meta) if !meta.is_nil() => {
+            let mut inner = vec![];
+            write_plain_value(&mut inner, value)?;
+            write_value(&mut inner, meta)?;
+            return write_ext(buf, EXT_META, &inner);
+        }
+        _ => {}
+    }
+    write_plain_value(buf, value)
+}
+
+fn write_plain_value(buf: &mut Vec<u8>, value: &MalType) -> Result<(), MalError> {
+    if value.is_nil() {
+        encode::write_nil(buf).map_err(write_error)
+    } else if value.is_true() {
+        encode::write_bool(buf, true).map_err(write_error)
+    } else if value.is_false() {
+        encode::write_bool(buf, false).map_err(write_error)
+    } else if let Some(number) = value.number_val() {
+        encode::write_sint(buf, number).map_err(write_error)?;
+        Ok(())
+    } else if let Some(string) = value.string_val() {
+        encode::write_str(buf, string).map_err(write_error)
+    } else if let Some(keyword) = value.keyword_val() {
+        write_ext(buf, EXT_KEYWORD, keyword.as_bytes())
+    } else if let Some(symbol) = value.symbol_val() {
+        write_ext(buf, EXT_SYMBOL, symbol.as_bytes())
+    } else if let Some(c) = value.char_val() {
+        write_ext(buf, EXT_CHAR, c.to_string().as_bytes())
+    } else if let Some(list) = value.list_val() {
+        let mut inner = vec![];
+        write_array(&mut inner, list.len(), list.iter())?;
+        write_ext(buf, EXT_LIST, &inner)
+    } else if let Some(vector) = value.vector_val() {
+        write_array(buf, vector.len(), vector.iter())
+    } else if let Some(set) = value.set_val() {
+        let mut inner = vec![];
+        write_array(&mut inner, set.len(), set.iter())?;
+        write_ext(buf, EXT_SET, &inner)
+    } else if let Some(map) = value.hashmap_val() {
+        encode::write_map_len(buf, map.len() as u32).map_err(write_error)?;
+        for (key, val) in map {
+            write_value(buf, key)?;
+            write_value(buf, val)?;
+        }
+        Ok(())
+    } else if value.is_function() || value.is_lambda() {
+        Err(MalError::WrongArguments(format!(
+            "Cannot encode function {:?}",
+            value
+        )))
+    } else if value.is_atom() {
+        Err(MalError::WrongArguments(format!(
+            "Cannot encode atom {:?}",
+            value
+        )))
+    } else {
+        Err(MalError::WrongArguments(format!("Cannot encode {:?}", value)))
+    }
+}
+
+fn write_array<'a, I>(buf: &mut Vec<u8>, len: usize, items: I) -> Result<(), MalError>
+where
+    I: Iterator<Item = &'a MalType>,
+{
+    encode::write_array_len(buf, len as u32).map_err(write_error)?;
+    for item in items {
+        write_value(buf, item)?;
+    }
+    Ok(())
+}
+
+fn write_ext(buf: &mut Vec<u8>, ext: i8, data: &[u8]) -> Result<(), MalError> {
+    encode::write_ext_meta(buf, data.len() as u32, ext).map_err(write_error)?;
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+fn write_error<E: Display>(err: E) -> MalError {
+    MalError::IO(err.to_string())
+}
+
+fn invalid<S: Display>(message: S) -> MalError {
+    MalError::Parse(format!("Invalid encoded value: {}", message))
+}
+
+fn read_value(rd: &mut &[u8], depth: usize) -> MalResult {
+    if depth > MAX_DEPTH {
+        return Err(invalid(format!("nested more than {} levels deep", MAX_DEPTH)));
+    }
+    // Peek at the marker without consuming it, so the rmp readers below can
+    // read the whole value.
+    let marker = decode::read_marker(&mut { *rd }).map_err(|_| invalid("unexpected end"))?;
+    match marker {
+        Marker::Null => {
+            decode::read_nil(rd).map_err(invalid)?;
+            Ok(MalType::nil())
+        }
+        Marker::True | Marker::False => {
+            if decode::read_bool(rd).map_err(invalid)? {
+                Ok(MalType::bool_true())
+            } else {
+                Ok(MalType::bool_false())
+            }
+        }
+        Marker::FixPos(_) | Marker::FixNeg(_) | Marker::U8 | Marker::U16 | Marker::U32
+        | Marker::U64 | Marker::I8 | Marker::I16 | Marker::I32 | Marker::I64 => {
+            Ok(MalType::number(decode::read_int(rd).map_err(invalid)?))
+        }
+        Marker::FixStr(_) | Marker::Str8 | Marker::Str16 | Marker::Str32 => {
+            let len = decode::read_str_len(rd).map_err(invalid)?;
+            Ok(MalType::string(read_utf8(rd, len)?))
+        }
+        Marker::FixArray(_) | Marker::Array16 | Marker::Array32 => {
+            Ok(MalType::vector(read_array(rd, depth)?))
+        }
+        Marker::FixMap(_) | Marker::Map16 | Marker::Map32 => {
+            let len = decode::read_map_len(rd).map_err(invalid)?;
+            let mut map = BTreeMap::new();
+            for _ in 0..len {
+                let key = read_value(rd, depth + 1)?;
+                map.insert(key, read_value(rd, depth + 1)?);
+            }
+            Ok(MalType::hashmap(map))
+        }
+        Marker::FixExt1 | Marker::FixExt2 | Marker::FixExt4 | Marker::FixExt8
+        | Marker::FixExt16 | Marker::Ext8 | Marker::Ext16 | Marker::Ext32 => read_ext(rd, depth),
+        marker => Err(invalid(format!("unsupported type {:?}", marker))),
+    }
+}
+
+fn read_ext(rd: &mut &[u8], depth: usize) -> MalResult {
+    let meta = decode::read_ext_meta(rd).map_err(invalid)?;
+    let data = take(rd, meta.size)?;
+    let mut inner = data;
+    let value = match meta.typeid {
+        EXT_KEYWORD => MalType::keyword(read_utf8(&mut inner, meta.size)?),
+        EXT_SYMBOL => MalType::symbol(read_utf8(&mut inner, meta.size)?),
+        EXT_CHAR => {
+            let string = read_utf8(&mut inner, meta.size)?;
+            let mut chars = string.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => MalType::char(c),
+                _ => return Err(invalid(format!("{:?} is not a single char", string))),
+            }
+        }
+        EXT_LIST => MalType::list(read_array(&mut inner, depth)?),
+        EXT_SET => {
+            let items = read_array(&mut inner, depth)?;
+            let set: BTreeSet<MalType> = items.into_iter().collect();
+            MalType::set(set)
+        }
+        EXT_META => {
+            let mut value = read_value(&mut inner, depth + 1)?;
+            let meta = read_value(&mut inner, depth + 1)?;
+            if value.get_metadata().is_none() {
+                return Err(invalid(format!("{:?} cannot have metadata", value)));
+            }
+            value.clone_with_meta(meta)
+        }
+        typeid => return Err(invalid(format!("unknown extension type {}", typeid))),
+    };
+    if inner.is_empty() {
+        Ok(value)
+    } else {
+        Err(invalid(format!("extension type {} is too long", meta.typeid)))
+    }
+}
+
+fn read_array(rd: &mut &[u8], depth: usize) -> Result<Vec<MalType>, MalError> {
+    let len = decode::read_array_len(rd).map_err(invalid)?;
+    let mut items = vec![];
+    for _ in 0..len {
+        items.push(read_value(rd, depth + 1)?);
+    }
+    Ok(items)
+}
+
+fn take<'a>(rd: &mut &'a [u8], len: u32) -> Result<&'a [u8], MalError> {
+    let len = len as usize;
+    if rd.len() < len {
+        return Err(invalid("unexpected end"));
+    }
+    let (data, rest) = rd.split_at(len);
+    *rd = rest;
+    Ok(data)
+}
+
+fn read_utf8(rd: &mut &[u8], len: u32) -> Result<String, MalError> {
+    let data = take(rd, len)?;
+    String::from_utf8(data.to_vec()).map_err(invalid)
+}
+
+/// `(encode value)` returns the encoding as a vector of byte numbers.
+pub fn encode_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let bytes = encode(&args[0])?;
+    Ok(MalType::vector(
+        bytes.into_iter().map(|b| MalType::number(i64::from(b))).collect(),
+    ))
+}
+
+/// `(decode bytes)` takes a list or vector of byte numbers from `encode`.
+pub fn decode_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut buf = vec![];
+    for byte in args[0].list_or_vector_val().unwrap() {
+        match byte.number_val() {
+            Some(n) if (0..=255).contains(&n) => buf.push(n as u8),
+            _ => {
+                return Err(MalError::WrongArguments(format!(
+                    "Expected a byte from 0 to 255 but got: {:?}",
+                    byte
+                )))
+            }
+        }
+    }
+    decode(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::read_str;
+
+    #[test]
+    fn test_round_trip() {
+        let code = r#"{:a (1 -200 [nil true false]) "b" #{sym \c 70000000000}}"#;
+        let value = read_str(code).unwrap();
+        assert_eq!(decode(&encode(&value).unwrap()).unwrap(), value);
+        let value = MalType::list(vec![MalType::keyword("x")])
+            .clone_with_meta(read_str("{:line 3}").unwrap());
+        let decoded = decode(&encode(&value).unwrap()).unwrap();
+        assert_eq!(decoded, value);
+        assert_eq!(decoded.get_metadata(), value.get_metadata());
+    }
+
+    #[test]
+    fn test_plain_msgpack() {
+        let value = read_str(r#"[1 "a" {"k" nil}]"#).unwrap();
+        assert_eq!(
+            encode(&value).unwrap(),
+            vec![0x93, 0x01, 0xa1, b'a', 0x81, 0xa1, b'k', 0xc0]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let atom = MalType::vector(vec![MalType::atom(MalType::nil())]);
+        assert_eq!(
+            encode(&atom).unwrap_err(),
+            MalError::WrongArguments("Cannot encode atom (atom nil)".to_string())
+        );
+        assert!(decode(&[0x92, 0x01]).is_err());
+        assert!(decode(&[0x01, 0x02]).is_err());
+        assert!(decode(&[0xd4, 99, 0]).is_err());
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let mut bytes = vec![0x91; 100_000];
+        bytes.push(0xc0);
+        assert_eq!(
+            decode(&bytes).unwrap_err(),
+            MalError::Parse("Invalid encoded value: nested more than 128 levels deep".to_string())
+        );
+        let mut bytes = vec![0x91; 128];
+        bytes.push(0xc0);
+        assert!(decode(&bytes).is_ok());
+    }
+}
@@ -1,136 +1,572 @@
 use types::*;
-use printer::pr_str;
-use reader::read_str;
+use printer::{pr_str, write_form, PrintOptions};
+use pprint::{pretty_str, PrettyOptions};
+use reader::{read_edn, read_str, DataReaders};
 use env::Env;
 use util::*;
+use json::{json_parse, json_stringify};
+use binary::{decode_fn, encode_fn};
+use files::*;
+use system::{exit, getenv, parse_args, setenv, sh};
 use readline::Readline;
+use output;
 
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
 use std::collections::HashMap;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+use regex::Captures;
 use time::get_time;
 
-lazy_static! {
-    pub static ref NS: HashMap<String, fn(&mut Vec<MalType>, Option<Env>) -> MalResult> = {
-        let mut ns: HashMap<String, fn(&mut Vec<MalType>, Option<Env>) -> MalResult>
-            = HashMap::new();
-        ns.insert("+".to_string(), add);
-        ns.insert("-".to_string(), subtract);
-        ns.insert("*".to_string(), multiply);
-        ns.insert("/".to_string(), divide);
-        ns.insert("prn".to_string(), prn);
-        ns.insert("println".to_string(), println_fn);
-        ns.insert("str".to_string(), str_fn);
-        ns.insert("pr-str".to_string(), pr_str_fn);
-        ns.insert("list".to_string(), list);
-        ns.insert("list?".to_string(), is_list);
-        ns.insert("vector".to_string(), vector);
-        ns.insert("vector?".to_string(), is_vector);
-        ns.insert("empty?".to_string(), is_empty);
-        ns.insert("count".to_string(), count);
-        ns.insert("=".to_string(), is_equal);
-        ns.insert("<".to_string(), is_lt);
-        ns.insert("<=".to_string(), is_lte);
-        ns.insert(">".to_string(), is_gt);
-        ns.insert(">=".to_string(), is_gte);
-        ns.insert("read-string".to_string(), read_string);
-        ns.insert("slurp".to_string(), slurp);
-        ns.insert("atom".to_string(), atom);
-        ns.insert("atom?".to_string(), is_atom);
-        ns.insert("deref".to_string(), deref);
-        ns.insert("reset!".to_string(), reset);
-        ns.insert("swap!".to_string(), swap);
-        ns.insert("cons".to_string(), cons);
-        ns.insert("concat".to_string(), concat);
-        ns.insert("nth".to_string(), nth);
-        ns.insert("first".to_string(), first);
-        ns.insert("rest".to_string(), rest);
-        ns.insert("throw".to_string(), throw);
-        ns.insert("apply".to_string(), apply);
-        ns.insert("map".to_string(), map);
-        ns.insert("nil?".to_string(), is_nil);
-        ns.insert("true?".to_string(), is_true);
-        ns.insert("false?".to_string(), is_false);
-        ns.insert("symbol".to_string(), symbol);
-        ns.insert("symbol?".to_string(), is_symbol);
-        ns.insert("keyword".to_string(), keyword);
-        ns.insert("keyword?".to_string(), is_keyword);
-        ns.insert("hash-map".to_string(), hash_map);
-        ns.insert("map?".to_string(), is_map);
-        ns.insert("assoc".to_string(), assoc);
-        ns.insert("dissoc".to_string(), dissoc);
-        ns.insert("get".to_string(), get);
-        ns.insert("contains?".to_string(), contains);
-        ns.insert("keys".to_string(), keys);
-        ns.insert("vals".to_string(), vals);
-        ns.insert("sequential?".to_string(), is_sequential);
-        ns.insert("readline".to_string(), readline);
-        ns.insert("meta".to_string(), meta);
-        ns.insert("with-meta".to_string(), with_meta);
-        ns.insert("string?".to_string(), is_string);
-        ns.insert("number?".to_string(), is_number);
-        ns.insert("fn?".to_string(), is_fn);
-        ns.insert("macro?".to_string(), is_macro);
-        ns.insert("conj".to_string(), conj);
-        ns.insert("seq".to_string(), seq);
-        ns.insert("gensym".to_string(), gensym);
-        ns.insert("time-ms".to_string(), time_ms);
-        ns
-    };
+/// Marks a builtin that takes any number of arguments after its minimum.
+const MANY: usize = usize::MAX;
+
+/// The kind of value a builtin expects for an argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    Any,
+    Number,
+    String,
+    /// A string or keyword, as names are given.
+    Name,
+    /// A number or char, as character codes are given.
+    NumberOrChar,
+    /// A string or regex to match against.
+    StringOrRegex,
+    /// A list, vector, set, map or nil, as accepted by `seq_result`.
+    Seq,
+    /// A list or vector.
+    Sequential,
+    /// Anything `seq` accepts: a collection, nil or a string.
+    Seqable,
+    Map,
+    /// A map, or nil for none, as options are given.
+    MapOrNil,
+    /// A map, set or nil to look keys up in.
+    Lookup,
+    Set,
+    Atom,
+    Regex,
+    File,
+    /// A function, or a keyword, map, set or vector called as a lookup.
+    Fn,
+}
+
+impl Param {
+    fn name(&self) -> &'static str {
+        match *self {
+            Param::Any => "any value",
+            Param::Number => "number",
+            Param::String => "string",
+            Param::Name => "string or keyword",
+            Param::NumberOrChar => "number or char",
+            Param::StringOrRegex => "string or regex",
+            Param::Seq => "collection",
+            Param::Sequential => "list or vector",
+            Param::Seqable => "collection or string",
+            Param::Map => "map",
+            Param::MapOrNil => "map or nil",
+            Param::Lookup => "map, set or nil",
+            Param::Set => "set",
+            Param::Atom => "atom",
+            Param::Regex => "regex",
+            Param::File => "file",
+            Param::Fn => "function",
+        }
+    }
+
+    fn accepts(&self, arg: &MalType) -> bool {
+        match *self {
+            Param::Any => true,
+            Param::Number => arg.is_number(),
+            Param::String => arg.is_string(),
+            Param::Name => arg.is_string() || arg.is_keyword(),
+            Param::NumberOrChar => arg.is_number() || arg.is_char(),
+            Param::StringOrRegex => arg.is_string() || arg.is_regex(),
+            Param::Seq => {
+                arg.is_nil() || arg.is_list_or_vector() || arg.is_set() || arg.is_hashmap()
+            }
+            Param::Sequential => arg.is_list_or_vector(),
+            Param::Seqable => Param::Seq.accepts(arg) || arg.is_string(),
+            Param::Map => arg.is_hashmap(),
+            Param::MapOrNil => arg.is_hashmap() || arg.is_nil(),
+            Param::Lookup => arg.is_hashmap() || arg.is_set() || arg.is_nil(),
+            Param::Set => arg.is_set(),
+            Param::Atom => arg.is_atom(),
+            Param::Regex => arg.is_regex(),
+            Param::File => arg.is_file(),
+            Param::Fn => {
+                arg.is_function() || arg.is_lambda() || arg.is_keyword() || arg.is_hashmap()
+                    || arg.is_set() || arg.is_vector()
+            }
+        }
+    }
+}
+
+/// How a builtin is called: its arity, the type of each argument, and the
+/// arglists and docstring shown by `doc`. Arguments past the end of `params`
+/// share its last type.
+pub struct Signature {
+    pub name: &'static str,
+    pub min: usize,
+    pub max: usize,
+    pub params: &'static [Param],
+    pub arglists: &'static str,
+    pub doc: &'static str,
 }
 
-fn assert_arg_count_gte(args: &Vec<MalType>, expected: usize, name: &str) -> Result<(), MalError> {
-    let actual = args.len();
-    if actual >= expected {
+impl Signature {
+    pub fn is_variadic(&self) -> bool {
+        self.max == MANY
+    }
+
+    /// Checks the arguments, naming the builtin and the first bad argument.
+    pub fn check(&self, args: &[MalType]) -> Result<(), MalError> {
+        if args.len() < self.min || args.len() > self.max {
+            let expected = if self.min == self.max {
+                plural(self.min)
+            } else if self.is_variadic() {
+                format!("at least {}", plural(self.min))
+            } else {
+                format!("{} to {} arguments", self.min, self.max)
+            };
+            return Err(MalError::WrongArguments(format!(
+                "`{}` expected {}, got {}",
+                self.name,
+                expected,
+                args.len()
+            )));
+        }
+        for (index, arg) in args.iter().enumerate() {
+            let param = self.params
+                .get(index)
+                .or_else(|| self.params.last())
+                .unwrap_or(&Param::Any);
+            if !param.accepts(arg) {
+                return Err(arg_error(self.name, param.name(), index, arg));
+            }
+        }
         Ok(())
+    }
+}
+
+fn plural(count: usize) -> String {
+    if count == 1 {
+        "1 argument".to_string()
     } else {
-        Err(MalError::WrongArguments(
-            format!(
-                "Must pass at least {} argument(s) to {} but only got {}",
-                expected, name, actual
-            ).to_string(),
-        ))
+        format!("{} arguments", count)
+    }
+}
+
+pub struct Builtin {
+    pub signature: Signature,
+    pub func: fn(&mut Vec<MalType>, Option<Env>) -> MalResult,
+}
+
+/// Declares the builtins as
+/// `"name" => function(min, max, [params]) "[arglist] ..." "doc";`.
+/// Each gets a wrapper in `checked` that checks its arguments against the
+/// signature before calling it, and an entry in `BUILTINS`.
+macro_rules! builtins {
+    ($($name:expr => $func:ident($min:expr, $max:expr, [$($param:ident),*])
+       $arglists:literal $doc:literal;)*) => {
+        mod checked {
+            use types::{MalResult, MalType};
+            use env::Env;
+            use super::{Param, Signature, MANY};
+
+            $(
+                pub fn $func(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+                    const SIGNATURE: Signature = Signature {
+                        name: $name,
+                        min: $min,
+                        max: $max,
+                        params: &[$(Param::$param),*],
+                        arglists: $arglists,
+                        doc: $doc,
+                    };
+                    SIGNATURE.check(args)?;
+                    super::$func(args, env)
+                }
+            )*
+        }
+
+        /// Every builtin, in the order they are declared.
+        pub static BUILTINS: &[Builtin] = &[
+            $(Builtin {
+                signature: Signature {
+                    name: $name,
+                    min: $min,
+                    max: $max,
+                    params: &[$(Param::$param),*],
+                    arglists: $arglists,
+                    doc: $doc,
+                },
+                func: checked::$func,
+            },)*
+        ];
+    };
+}
+
+builtins! {
+    "+" => add(1, MANY, [Number]) "[x & more]"
+        "Returns the sum of the numbers.";
+    "-" => subtract(1, MANY, [Number]) "[x & more]"
+        "Subtracts the rest of the numbers from the first.";
+    "*" => multiply(1, MANY, [Number]) "[x & more]"
+        "Returns the product of the numbers.";
+    "/" => divide(1, MANY, [Number]) "[x & more]"
+        "Divides the first number by the rest, rounding towards zero.";
+    "prn" => prn(0, MANY, [Any]) "[& xs]"
+        "Prints the values readably, separated by spaces, followed by a newline.";
+    "println" => println_fn(0, MANY, [Any]) "[& xs]"
+        "Prints the values for people to read, separated by spaces, followed by a newline.";
+    "str" => str_fn(0, MANY, [Any]) "[& xs]"
+        "Returns the values printed for people to read, with nothing between them.";
+    "pr-str" => pr_str_fn(0, MANY, [Any]) "[& xs]"
+        "Returns the values printed readably, separated by spaces.";
+    "pprint" => pprint(1, 2, [Any, Number]) "[x] [x width]"
+        "Pretty-prints a value, wrapping lines at the given width or 80 columns. Follows *print-length* and *print-level*.";
+    "list" => list(0, MANY, [Any]) "[& items]"
+        "Returns a list of the arguments.";
+    "list?" => is_list(1, 1, [Any]) "[x]"
+        "True when the value is a list.";
+    "vector" => vector(0, MANY, [Any]) "[& items]"
+        "Returns a vector of the arguments.";
+    "vector?" => is_vector(1, 1, [Any]) "[x]"
+        "True when the value is a vector.";
+    "empty?" => is_empty(1, 1, [Seq]) "[coll]"
+        "True when the collection has no items.";
+    "count" => count(1, 1, [Seq]) "[coll]"
+        "Returns the number of items in the collection.";
+    "=" => is_equal(2, 2, [Any]) "[x y]"
+        "True when the two values are equal.";
+    "<" => is_lt(2, 2, [Any]) "[x y]"
+        "True when the first number or char is less than the second.";
+    "<=" => is_lte(2, 2, [Any]) "[x y]"
+        "True when the first number or char is less than or equal to the second.";
+    ">" => is_gt(2, 2, [Any]) "[x y]"
+        "True when the first number or char is greater than the second.";
+    ">=" => is_gte(2, 2, [Any]) "[x y]"
+        "True when the first number or char is greater than or equal to the second.";
+    "read-string" => read_string(1, 1, [String]) "[s]"
+        "Reads the first form in the string.";
+    "read-edn" => read_edn_fn(1, 1, [String]) "[s]"
+        "Reads the first EDN form in the string, using the tag readers in *data-readers*.";
+    "slurp" => slurp(1, 1, [String]) "[path]"
+        "Returns the contents of the file at the path.";
+    "spit" => spit(2, 3, [String, Any, MapOrNil]) "[path x] [path x opts]"
+        "Writes the value, as by str, to the file at the path, appending when passed {:append true}.";
+    "open-reader" => open_reader(1, 1, [String]) "[path]"
+        "Opens the file at the path for read-line and line-seq, or for binding *in*.";
+    "open-writer" => open_writer(1, 2, [String, MapOrNil]) "[path] [path opts]"
+        "Opens the file at the path for writing by binding *out*, appending when passed {:append true}.";
+    "read-line" => read_line(0, 1, [File]) "[] [file]"
+        "Returns the next line of the file or *in*, or nil at the end.";
+    "line-seq" => line_seq(0, 1, [File]) "[] [file]"
+        "Returns a list of the rest of the lines of the file or *in*.";
+    "read-all-stdin" => read_all_stdin(0, 0, [Any]) "[]"
+        "Returns the rest of *in*, stdin unless bound to a reader, as a string.";
+    "close" => close(1, 1, [File]) "[file]"
+        "Closes the file. with-open calls this for you.";
+    "file?" => is_file(1, 1, [Any]) "[x]"
+        "True when the value is a file opened by open-reader or open-writer.";
+    "file-exists?" => file_exists(1, 1, [String]) "[path]"
+        "True when there is a file or directory at the path.";
+    "directory?" => is_directory(1, 1, [String]) "[path]"
+        "True when the path is a directory.";
+    "delete-file" => delete_file(1, 1, [String]) "[path]"
+        "Deletes the file or empty directory at the path.";
+    "mkdir" => mkdir(1, 1, [String]) "[path]"
+        "Creates the directory at the path, and any missing parents.";
+    "list-dir" => list_dir(1, 1, [String]) "[path]"
+        "Returns a sorted vector of the names in the directory.";
+    "walk-dir" => walk_dir(1, 1, [String]) "[path]"
+        "Returns a vector of the paths of everything under the directory, each directory before its contents. Symlinks are not followed.";
+    "path-join" => path_join(1, MANY, [String]) "[path & parts]"
+        "Joins the parts onto the path with the separator.";
+    "basename" => basename(1, 1, [String]) "[path]"
+        "Returns the last part of the path, or nil.";
+    "extension" => extension(1, 1, [String]) "[path]"
+        "Returns the extension of the path without the dot, or nil.";
+    "atom" => atom(1, 1, [Any]) "[x]"
+        "Returns an atom holding the value.";
+    "atom?" => is_atom(1, 1, [Any]) "[x]"
+        "True when the value is an atom.";
+    "deref" => deref(1, 1, [Atom]) "[atom]"
+        "Returns the value held by the atom.";
+    "reset!" => reset(2, 2, [Atom, Any]) "[atom x]"
+        "Sets the value of the atom and returns it.";
+    "swap!" => swap(2, MANY, [Atom, Fn, Any]) "[atom f & args]"
+        "Sets the atom to (f old-value & args) and returns the new value.";
+    "cons" => cons(2, 2, [Any, Seq]) "[x coll]"
+        "Returns a list of the value followed by the collection.";
+    "concat" => concat(0, MANY, [Seq]) "[& colls]"
+        "Returns a list of the items of each collection in turn.";
+    "nth" => nth(2, 2, [Seq, Number]) "[coll index]"
+        "Returns the item at the index, counting from 0.";
+    "first" => first(1, 1, [Seq]) "[coll]"
+        "Returns the first item of the collection, or nil.";
+    "rest" => rest(1, 1, [Seq]) "[coll]"
+        "Returns a list of all but the first item of the collection.";
+    "throw" => throw(1, 1, [Any]) "[x]"
+        "Throws the value, to be caught by try*.";
+    "ex-info" => ex_info(1, 2, [String, MapOrNil]) "[message] [message data]"
+        "Returns an error with a message and a map of data, to be thrown.";
+    "ex-data" => ex_data(1, 1, [Any]) "[e]"
+        "Returns the data map of an ex-info error, or nil.";
+    "ex-message" => ex_message(1, 1, [Any]) "[e]"
+        "Returns the message of an ex-info error, or nil.";
+    "apply" => apply(2, MANY, [Fn, Any]) "[f & args coll]"
+        "Calls the function with the arguments, the last of which is a collection of more.";
+    "map" => map(2, MANY, [Fn, Seq]) "[f coll & colls]"
+        "Returns a list of f called with an item from each collection in turn.";
+    "mapcat" => mapcat(2, MANY, [Fn, Seq]) "[f coll & colls]"
+        "Returns the concatenation of the collections that map returns.";
+    "filter" => filter(2, 2, [Fn, Seq]) "[pred coll]"
+        "Returns a list of the items for which the predicate is truthy.";
+    "remove" => remove(2, 2, [Fn, Seq]) "[pred coll]"
+        "Returns a list of the items for which the predicate is falsey.";
+    "reduce" => reduce(2, 3, [Fn, Any, Any]) "[f coll] [f init coll]"
+        "Combines the items with f, starting from init or the first item.";
+    "some" => some(2, 2, [Fn, Seq]) "[pred coll]"
+        "Returns the first truthy result of the predicate on the items, or nil.";
+    "every?" => is_every(2, 2, [Fn, Seq]) "[pred coll]"
+        "True when the predicate is truthy for every item.";
+    "take-while" => take_while(2, 2, [Fn, Seq]) "[pred coll]"
+        "Returns a list of the leading items for which the predicate is truthy.";
+    "drop-while" => drop_while(2, 2, [Fn, Seq]) "[pred coll]"
+        "Returns a list of the items after those for which the predicate is truthy.";
+    "partition" => partition(2, 4, [Number, Any, Any, Any]) "[n coll] [n step coll] [n step pad coll]"
+        "Splits the collection into lists of n items, every step items, padded from pad.";
+    "interleave" => interleave(0, MANY, [Seq]) "[& colls]"
+        "Returns a list of the first item of each collection, then the second, and so on.";
+    "distinct" => distinct(1, 1, [Seq]) "[coll]"
+        "Returns a list of the items without duplicates.";
+    "frequencies" => frequencies(1, 1, [Seq]) "[coll]"
+        "Returns a map from each item to the number of times it appears.";
+    "group-by" => group_by(2, 2, [Fn, Seq]) "[f coll]"
+        "Returns a map from each result of f to a vector of the items that gave it.";
+    "sort" => sort(1, 2, [Any, Any]) "[coll] [comparator coll]"
+        "Returns a sorted list of the items, optionally using a comparator.";
+    "sort-by" => sort_by(2, 3, [Fn, Any, Any]) "[keyfn coll] [keyfn comparator coll]"
+        "Returns a list of the items sorted by the result of keyfn on each.";
+    "reverse" => reverse(1, 1, [Seq]) "[coll]"
+        "Returns a list of the items in reverse order.";
+    "nil?" => is_nil(1, 1, [Any]) "[x]"
+        "True when the value is nil.";
+    "true?" => is_true(1, 1, [Any]) "[x]"
+        "True when the value is true.";
+    "false?" => is_false(1, 1, [Any]) "[x]"
+        "True when the value is false.";
+    "symbol" => symbol(1, 1, [String]) "[name]"
+        "Returns a symbol with the name.";
+    "symbol?" => is_symbol(1, 1, [Any]) "[x]"
+        "True when the value is a symbol.";
+    "keyword" => keyword(1, 1, [Name]) "[name]"
+        "Returns a keyword with the name.";
+    "keyword?" => is_keyword(1, 1, [Any]) "[x]"
+        "True when the value is a keyword.";
+    "hash-map" => hash_map(0, MANY, [Any]) "[& keyvals]"
+        "Returns a map of the keys and values.";
+    "map?" => is_map(1, 1, [Any]) "[x]"
+        "True when the value is a map.";
+    "set" => set(1, 1, [Seq]) "[coll]"
+        "Returns a set of the items in the collection.";
+    "hash-set" => hash_set(0, MANY, [Any]) "[& items]"
+        "Returns a set of the arguments.";
+    "set?" => is_set(1, 1, [Any]) "[x]"
+        "True when the value is a set.";
+    "disj" => disj(1, MANY, [Set, Any]) "[set & items]"
+        "Returns the set without the values.";
+    "assoc" => assoc(1, MANY, [Map, Any]) "[map & keyvals]"
+        "Returns the map with the keys set to the values.";
+    "dissoc" => dissoc(1, MANY, [Map, Any]) "[map & keys]"
+        "Returns the map without the keys.";
+    "get" => get(2, 2, [Lookup, Any]) "[coll key]"
+        "Returns the value for the key in a map or set, or nil.";
+    "contains?" => contains(2, 2, [Lookup, Any]) "[coll key]"
+        "True when the map or set has the key.";
+    "keys" => keys(1, 1, [Map]) "[map]"
+        "Returns a list of the keys of the map.";
+    "vals" => vals(1, 1, [Map]) "[map]"
+        "Returns a list of the values of the map.";
+    "get-in" => get_in(2, 3, [Any, Sequential, Any]) "[coll path] [coll path default]"
+        "Returns the value at the path of keys in nested collections, or the default.";
+    "assoc-in" => assoc_in(3, 3, [Any, Sequential, Any]) "[coll path value]"
+        "Returns the nested collections with the value at the path of keys set.";
+    "update" => update(3, MANY, [Any, Any, Fn, Any]) "[coll key f & args]"
+        "Returns the collection with the key set to (f old-value & args).";
+    "update-in" => update_in(3, MANY, [Any, Sequential, Fn, Any]) "[coll path f & args]"
+        "Returns the nested collections with the value at the path set to (f old-value & args).";
+    "merge" => merge(0, MANY, [MapOrNil]) "[& maps]"
+        "Returns the maps merged from left to right, the last value for a key winning.";
+    "merge-with" => merge_with(1, MANY, [Fn, MapOrNil]) "[f & maps]"
+        "Merges the maps, combining the values for a key with (f old new).";
+    "select-keys" => select_keys(2, 2, [Any, Seq]) "[map keys]"
+        "Returns a map of just the given keys that are in the map.";
+    "zipmap" => zipmap(2, 2, [Seq, Seq]) "[keys vals]"
+        "Returns a map pairing each key with a value.";
+    "into" => into(2, 2, [Seq, Seq]) "[to from]"
+        "Adds each item of the second collection to the first.";
+    "reduce-kv" => reduce_kv(3, 3, [Fn, Any, Any]) "[f init coll]"
+        "Combines the entries of a map or vector with (f acc key value), starting from init.";
+    "sequential?" => is_sequential(1, 1, [Any]) "[x]"
+        "True when the value is a list or vector.";
+    "readline" => readline(0, 1, [String]) "[] [prompt]"
+        "Reads a line from the terminal after the prompt, or nil at the end of input.";
+    "meta" => meta(1, 1, [Any]) "[x]"
+        "Returns the metadata of the value, or nil.";
+    "with-meta" => with_meta(2, 2, [Any, Any]) "[x meta]"
+        "Returns the value with the metadata.";
+    "string?" => is_string(1, 1, [Any]) "[x]"
+        "True when the value is a string.";
+    "number?" => is_number(1, 1, [Any]) "[x]"
+        "True when the value is a number.";
+    "char" => char_fn(1, 1, [NumberOrChar]) "[code]"
+        "Returns the character with the code.";
+    "char?" => is_char(1, 1, [Any]) "[x]"
+        "True when the value is a character.";
+    "int" => int(1, 1, [NumberOrChar]) "[c]"
+        "Returns the code of the character.";
+    "fn?" => is_fn(1, 1, [Any]) "[x]"
+        "True when the value is a function and not a macro.";
+    "macro?" => is_macro(1, 1, [Any]) "[x]"
+        "True when the value is a macro.";
+    "conj" => conj(2, MANY, [Seq, Any]) "[coll & items]"
+        "Adds the values to the collection: at the front of a list or nil, the end of a vector, or as [key value] pairs to a map.";
+    "seq" => seq(1, 1, [Seqable]) "[coll]"
+        "Returns a list of the items or characters, or nil when there are none.";
+    "subs" => subs(2, 3, [String, Number, Number]) "[s start] [s start end]"
+        "Returns the characters from start up to end or the end of the string.";
+    "split" => split(2, 2, [String, StringOrRegex]) "[s separator]"
+        "Splits the string on a string or regex.";
+    "join" => join(1, 2, [Any, Seq]) "[coll] [separator coll]"
+        "Joins the items printed for people to read, with an optional separator.";
+    "upper-case" => upper_case(1, 1, [String]) "[s]"
+        "Returns the string in upper case.";
+    "lower-case" => lower_case(1, 1, [String]) "[s]"
+        "Returns the string in lower case.";
+    "trim" => trim(1, 1, [String]) "[s]"
+        "Returns the string without leading or trailing whitespace.";
+    "replace" => replace(3, 3, [String, StringOrRegex, Any]) "[s match replacement]"
+        "Replaces every match of a string or regex with a string or (f match).";
+    "starts-with?" => starts_with(2, 2, [String, String]) "[s prefix]"
+        "True when the string starts with the prefix.";
+    "ends-with?" => ends_with(2, 2, [String, String]) "[s suffix]"
+        "True when the string ends with the suffix.";
+    "index-of" => index_of(2, 3, [String, String, Number]) "[s value] [s value from]"
+        "Returns the index of the first occurrence of the value from an optional start, or nil.";
+    "includes?" => includes(2, 2, [String, String]) "[s value]"
+        "True when the string contains the value.";
+    "blank?" => is_blank(1, 1, [Any]) "[s]"
+        "True when the value is nil or a string of whitespace.";
+    "format" => format_fn(1, MANY, [String, Any]) "[template & args]"
+        "Formats the values printf-style, with %s, %d, %x, %c and friends.";
+    "regex?" => is_regex(1, 1, [Any]) "[x]"
+        "True when the value is a regex.";
+    "re-pattern" => re_pattern(1, 1, [StringOrRegex]) "[s]"
+        "Returns a regex compiled from the string.";
+    "re-find" => re_find(2, 2, [Regex, String]) "[re s]"
+        "Returns the first match of the regex in the string, or nil.";
+    "re-matches" => re_matches(2, 2, [Regex, String]) "[re s]"
+        "Returns the match when the regex matches the whole string, or nil.";
+    "re-seq" => re_seq(2, 2, [Regex, String]) "[re s]"
+        "Returns a list of every match of the regex in the string, or nil.";
+    "re-groups" => re_groups(2, 2, [Regex, String]) "[re s]"
+        "Returns a vector of the first match of the regex and all of its groups, or nil.";
+    "json-parse" => json_parse(1, 2, [String, MapOrNil]) "[s] [s opts]"
+        "Parses a JSON string, with keyword keys when passed {:keywordize true}.";
+    "json-stringify" => json_stringify(1, 2, [Any, MapOrNil]) "[x] [x opts]"
+        "Returns the value as JSON, indented when passed {:pretty true}.";
+    "encode" => encode_fn(1, 1, [Any]) "[x]"
+        "Returns the value encoded as a vector of bytes.";
+    "decode" => decode_fn(1, 1, [Sequential]) "[bytes]"
+        "Returns the value encoded in the vector of bytes.";
+    "sh" => sh(1, MANY, [String, Any]) "[cmd & args] [cmd & args opts]"
+        "Runs the command and returns {:exit :out :err}. Takes {:in s :dir path} options last.";
+    "getenv" => getenv(0, 1, [String]) "[] [name]"
+        "Returns the environment variable, or nil, or a map of them all.";
+    "setenv" => setenv(2, 2, [String, Any]) "[name value]"
+        "Sets the environment variable, or removes it when the value is nil.";
+    "exit" => exit(0, 1, [Number]) "[] [status]"
+        "Exits the program with the status from 0 to 255, 0 by default. try* does not catch it.";
+    "parse-args" => parse_args(1, 2, [Seq, Seq]) "[argv] [argv takes-value]"
+        "Splits arguments like *ARGV* into {:options {...} :args [...]}.";
+    "doc" => doc(1, 1, [Any]) "[name]"
+        "Prints the arglists and docstring of the value bound to a quoted name.";
+    "find-doc" => find_doc(1, 1, [StringOrRegex]) "[pattern]"
+        "Prints the docs of every global whose name or docstring matches a string or regex.";
+    "apropos" => apropos(1, 1, [StringOrRegex]) "[pattern]"
+        "Returns a list of the global symbols whose names match a string or regex.";
+    "fn-args" => fn_args(1, 1, [Any]) "[f]"
+        "Returns a vector of the parameters of a function, or nil for a builtin.";
+    "fn-body" => fn_body(1, 1, [Any]) "[f]"
+        "Returns the body of a function, or nil for a builtin.";
+    "source" => source(1, 1, [Any]) "[name]"
+        "Prints the def! or defmacro! form, as read, that defined a quoted name.";
+    "gensym" => gensym(0, 0, []) "[]"
+        "Returns a new, unique symbol.";
+    "time-ms" => time_ms(0, 0, []) "[]"
+        "Returns the milliseconds since the Unix epoch.";
+}
+
+lazy_static! {
+    pub static ref NS: HashMap<String, fn(&mut Vec<MalType>, Option<Env>) -> MalResult> =
+        BUILTINS
+            .iter()
+            .map(|builtin| (builtin.signature.name.to_string(), builtin.func))
+            .collect();
+}
+
+/// Splits the arguments of an arithmetic builtin into the first number and
+/// the rest.
+fn numbers(args: &[MalType]) -> Result<(i64, Vec<i64>), MalError> {
+    let nums = args.iter().map(num_result).collect::<Result<Vec<i64>, MalError>>()?;
+    match nums.split_first() {
+        Some((first, rest)) => Ok((*first, rest.to_vec())),
+        None => Err(MalError::WrongArguments(
+            "Must pass at least one number".to_string(),
+        )),
     }
 }
 
 pub fn add(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "+")?;
-    let mut iter = MalNumberIter { items: args };
-    let mut answer = iter.next().unwrap()?;
-    for num in iter {
-        answer += num?;
+    let (first, rest) = numbers(args)?;
+    let mut answer = first;
+    for num in rest {
+        answer += num;
     }
     Ok(MalType::number(answer))
 }
 
 pub fn subtract(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "-")?;
-    let mut iter = MalNumberIter { items: args };
-    let mut answer = iter.next().unwrap()?;
-    for num in iter {
-        answer -= num?;
+    let (first, rest) = numbers(args)?;
+    let mut answer = first;
+    for num in rest {
+        answer -= num;
     }
     Ok(MalType::number(answer))
 }
 
 pub fn multiply(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "*")?;
-    let mut iter = MalNumberIter { items: args };
-    let mut answer = iter.next().unwrap()?;
-    for num in iter {
-        answer *= num?;
+    let (first, rest) = numbers(args)?;
+    let mut answer = first;
+    for num in rest {
+        answer *= num;
     }
     Ok(MalType::number(answer))
 }
 
 pub fn divide(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "/")?;
-    let mut iter = MalNumberIter { items: args };
-    let mut answer = iter.next().unwrap()?;
-    for num in iter {
-        let num = num?;
+    let (first, rest) = numbers(args)?;
+    let mut answer = first;
+    for num in rest {
         if num == 0 {
             return Err(MalError::DivideByZero);
         } else {
@@ -140,39 +576,79 @@ pub fn divide(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     Ok(MalType::number(answer))
 }
 
-fn _println(args: &mut Vec<MalType>, print_readably: bool, joiner: &str) -> MalResult {
-    let results: Vec<String> = args.iter().map(|arg| pr_str(arg, print_readably)).collect();
-    let out = results.join(joiner);
-    println!("{}", out);
+fn _println(args: &mut Vec<MalType>, env: &Option<Env>, opts: &PrintOptions, joiner: &str) -> MalResult {
+    output::write_to(&output::out(env), |mut out| {
+        write_forms(&mut out, args, opts, joiner)?;
+        out.write_all(b"\n")
+    })?;
     Ok(MalType::nil())
 }
 
-fn println_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    _println(args, false, " ")
+/// Writes a line of text to `*out*`.
+fn print_line(env: &Option<Env>, text: &str) -> Result<(), MalError> {
+    output::write_to(&output::out(env), |out| writeln!(out, "{}", text))
+}
+
+fn write_forms<W: Write>(
+    out: &mut W,
+    args: &[MalType],
+    opts: &PrintOptions,
+    joiner: &str,
+) -> io::Result<()> {
+    for (index, arg) in args.iter().enumerate() {
+        if index > 0 {
+            out.write_all(joiner.as_bytes())?;
+        }
+        write_form(out, arg, opts)?;
+    }
+    Ok(())
+}
+
+fn print_options(env: &Option<Env>, print_readably: bool) -> PrintOptions {
+    match *env {
+        Some(ref env) => PrintOptions::from_env(env, print_readably),
+        None => PrintOptions::readably(print_readably),
+    }
+}
+
+fn println_fn(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    _println(args, &env, &print_options(&env, false), " ")
 }
 
-fn prn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    _println(args, true, " ")
+fn prn(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    _println(args, &env, &print_options(&env, true), " ")
 }
 
-fn _str_fn(args: &mut Vec<MalType>, print_readably: bool, joiner: &str) -> MalResult {
-    let results: Vec<String> = args.iter().map(|arg| pr_str(arg, print_readably)).collect();
-    Ok(MalType::string(results.join(joiner)))
+fn _str_fn(args: &mut Vec<MalType>, opts: &PrintOptions, joiner: &str) -> MalResult {
+    let mut out = vec![];
+    write_forms(&mut out, args, opts, joiner)?;
+    Ok(MalType::string(
+        String::from_utf8(out).expect("printer wrote invalid UTF-8"),
+    ))
 }
 
 fn str_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    _str_fn(args, false, "")
+    _str_fn(args, &PrintOptions::readably(false), "")
 }
 
-fn pr_str_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    _str_fn(args, true, " ")
+fn pr_str_fn(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    _str_fn(args, &print_options(&env, true), " ")
+}
+
+fn pprint(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let mut opts = PrettyOptions::default().print(print_options(&env, true));
+    if args.len() > 1 {
+        opts.width = num_result(&args[1])?.max(0) as usize;
+    }
+    print_line(&env, &pretty_str(&args[0], &opts))?;
+    Ok(MalType::nil())
 }
 
 fn list(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     Ok(MalType::list(args.clone()))
 }
 
-fn mal_bool(b: bool) -> MalType {
+pub fn mal_bool(b: bool) -> MalType {
     if b {
         MalType::bool_true()
     } else {
@@ -181,7 +657,6 @@ fn mal_bool(b: bool) -> MalType {
 }
 
 fn is_list(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "list?")?;
     Ok(mal_bool(args[0].is_list()))
 }
 
@@ -190,29 +665,15 @@ fn vector(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn is_vector(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "vector?")?;
     Ok(mal_bool(args[0].is_vector()))
 }
 
 fn is_empty(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "empty?")?;
-    let arg = args.remove(0);
-    let vec = vec_result(&arg)?;
-    Ok(mal_bool(vec.len() == 0))
+    Ok(mal_bool(seq_result(&args[0])?.is_empty()))
 }
 
 fn count(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "count")?;
-    let arg = &args[0];
-    if let Some(vec) = arg.list_or_vector_val() {
-        Ok(MalType::number(vec.len() as i64))
-    } else if arg.is_nil() {
-        Ok(MalType::number(0))
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass a list or vector to count".to_string(),
-        ))
-    }
+    Ok(MalType::number(seq_result(&args[0])?.len() as i64))
 }
 
 fn are_lists_equal(list1: &MalType, list2: &MalType) -> bool {
@@ -263,27 +724,16 @@ fn is_equal_bool(val1: &MalType, val2: &MalType) -> bool {
 }
 
 fn is_equal(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    if args.len() == 2 {
-        let arg1 = args.remove(0);
-        let arg2 = args.remove(0);
-        Ok(mal_bool(is_equal_bool(&arg1, &arg2)))
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass exactly two arguments to =".to_string(),
-        ))
-    }
+    Ok(mal_bool(is_equal_bool(&args[0], &args[1])))
 }
 
 fn num_compare(args: &mut Vec<MalType>, compare: &Fn(i64, i64) -> bool) -> MalResult {
-    if args.len() == 2 {
-        let n1 = num_result(&args.remove(0))?;
-        let n2 = num_result(&args.remove(0))?;
-        Ok(mal_bool(compare(n1, n2)))
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass exactly two arguments to compare".to_string(),
-        ))
+    if let (Some(c1), Some(c2)) = (args[0].char_val(), args[1].char_val()) {
+        return Ok(mal_bool(compare(c1 as i64, c2 as i64)));
     }
+    let n1 = num_result(&args[0])?;
+    let n2 = num_result(&args[1])?;
+    Ok(mal_bool(compare(n1, n2)))
 }
 
 fn is_lt(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
@@ -303,77 +753,68 @@ fn is_gte(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn read_string(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "read-string")?;
-    if let Some(code) = args.remove(0).string_val() {
-        read_str(code)
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass a string to read_string".to_string(),
-        ))
+    read_str(string_arg(args, 0, "read-string")?)
+}
+
+/// Reads EDN, using the functions in `*data-readers*` (a map from tag
+/// symbol to function) alongside the built-in `#inst` and `#uuid` readers.
+fn read_edn_fn(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let mut readers = DataReaders::default();
+    if let Some(map) = env.and_then(|env| env.get("*data-readers*").ok()) {
+        if let Some(map) = map.hashmap_val() {
+            for (tag, func) in map {
+                let name = match (tag.symbol_val(), tag.keyword_val(), tag.string_val()) {
+                    (Some(name), _, _) | (_, Some(name), _) | (_, _, Some(name)) => name,
+                    _ => {
+                        return Err(MalError::WrongArguments(format!(
+                            "*data-readers* keys must be symbols but got: {:?}",
+                            tag
+                        )))
+                    }
+                };
+                let func = func.clone();
+                readers.register(name, move |value| eval_func(func.clone(), &mut vec![value]));
+            }
+        }
     }
+    read_edn(string_arg(args, 0, "read-edn")?, &readers)
 }
 
 fn slurp(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "slurp")?;
-    if let Some(path) = args.remove(0).string_val() {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        Ok(MalType::string(contents))
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass a string to slurp".to_string(),
-        ))
-    }
+    let mut file = File::open(string_arg(args, 0, "slurp")?)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(MalType::string(contents))
 }
 
 fn atom(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "atom")?;
     Ok(MalType::atom(args.remove(0)))
 }
 
 fn is_atom(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "atom?")?;
     Ok(mal_bool(args.remove(0).is_atom()))
 }
 
 fn deref(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "deref")?;
-    if let Some(val) = args.remove(0).atom_val() {
-        Ok(val.borrow().clone())
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass an atom to deref".to_string(),
-        ))
-    }
+    Ok(args[0].atom_val().unwrap().borrow().clone())
 }
 
 fn reset(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "reset!")?;
-    let atom = args.remove(0);
-    let new_val = args.remove(0);
-    if let Some(ref mut val) = atom.atom_val() {
-        val.replace(new_val.clone());
-        Ok(new_val)
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass an atom to reset".to_string(),
-        ))
-    }
+    let new_val = args.remove(1);
+    args[0].atom_val().unwrap().replace(new_val.clone());
+    Ok(new_val)
 }
 
 fn swap(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "swap")?;
     let mut atom = args.remove(0);
     let func = args.remove(0);
     atom.swap(func, args)
 }
 
 fn cons(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "cons")?;
     let item = args.remove(0);
     let list = args.remove(0);
-    let mut vec = vec_result(&list)?;
+    let mut vec = seq_result(&list)?;
     vec.insert(0, item);
     Ok(MalType::list(vec))
 }
@@ -381,7 +822,7 @@ fn cons(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 fn concat(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     let mut result = vec![];
     while args.len() > 0 {
-        let vec = vec_result(&args.remove(0))?;
+        let vec = seq_result(&args.remove(0))?;
         for item in vec {
             result.push(item);
         }
@@ -390,10 +831,9 @@ fn concat(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn nth(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "nth")?;
     let list = args.remove(0);
     let index = num_result(&args.remove(0))? as usize;
-    let vec = vec_result(&list)?;
+    let vec = seq_result(&list)?;
     if vec.len() > index {
         Ok(vec[index].clone())
     } else {
@@ -405,49 +845,53 @@ fn nth(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn first(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "first")?;
-    let list = args.remove(0);
-    if let Some(vec) = list.list_or_vector_val() {
-        if vec.len() > 0 {
-            Ok(vec[0].clone())
-        } else {
-            Ok(MalType::nil())
-        }
-    } else if list.is_nil() {
-        Ok(MalType::nil())
-    } else {
-        Err(MalError::WrongArguments(
-            format!("Expected a list passed to first but got: {:?}", list).to_string(),
-        ))
-    }
+    Ok(seq_result(&args[0])?
+        .into_iter()
+        .next()
+        .unwrap_or_else(MalType::nil))
 }
 
 fn rest(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "rest")?;
-    let list = args.remove(0);
-    if let Some(vec) = list.list_or_vector_val() {
-        if vec.len() > 0 {
-            Ok(MalType::list(vec[1..].to_owned()))
-        } else {
-            Ok(MalType::list(vec![]))
-        }
-    } else if list.is_nil() {
-        Ok(MalType::list(vec![]))
-    } else {
-        Err(MalError::WrongArguments(
-            format!("Expected a list passed to rest but got: {:?}", list).to_string(),
-        ))
-    }
+    Ok(MalType::list(seq_result(&args[0])?.into_iter().skip(1).collect()))
 }
 
 fn throw(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "throw")?;
     let val = args.remove(0);
     Err(MalError::Generic(val))
 }
 
+/// `(ex-info message data)` makes an error value to `throw`. A `:type` in
+/// `data` is matched by typed `catch*` clauses.
+fn ex_info(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let message = string_arg(args, 0, "ex-info")?;
+    let data = match args.get(1) {
+        Some(data) if data.is_hashmap() => data.clone(),
+        _ => MalType::hashmap(BTreeMap::new()),
+    };
+    Ok(MalType::ex_info(message, data))
+}
+
+/// The data map of an `ex-info` value, or nil for anything else.
+fn ex_data(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(args[0]
+        .ex_info_val()
+        .map(|(_, data)| data.clone())
+        .unwrap_or_else(MalType::nil))
+}
+
+/// The message of an `ex-info` value. A string, as bound by an untyped
+/// `catch*` for built-in errors, is its own message.
+fn ex_message(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    if let Some((message, _)) = args[0].ex_info_val() {
+        Ok(MalType::string(message))
+    } else if args[0].is_string() {
+        Ok(args[0].clone())
+    } else {
+        Ok(MalType::nil())
+    }
+}
+
 fn apply(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "apply")?;
     let func = args.remove(0);
     let last_index = args.len() - 1;
     let list = args.remove(last_index);
@@ -457,65 +901,353 @@ fn apply(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     eval_func(func, args)
 }
 
+/// `(map f coll & colls)` calls `f` with one item from each collection,
+/// stopping at the end of the shortest.
 fn map(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "map")?;
     let lambda = args.remove(0);
-    let list = args.remove(0);
+    if args.len() == 1 {
+        let mut result_list = vec![];
+        for item in seq_result(&args[0])? {
+            let mut args = vec![item];
+            let result = eval_func(lambda.clone(), &mut args)?;
+            result_list.push(result);
+        }
+        return Ok(MalType::list(result_list));
+    }
+    let colls: Result<Vec<Vec<MalType>>, MalError> = args.iter().map(seq_result).collect();
+    let colls = colls?;
+    let len = colls.iter().map(Vec::len).min().unwrap_or(0);
     let mut result_list = vec![];
-    for item in vec_result(&list)? {
-        let mut args = vec![item];
-        let result = eval_func(lambda.clone(), &mut args)?;
-        result_list.push(result);
+    for index in 0..len {
+        let mut args: Vec<MalType> = colls.iter().map(|coll| coll[index].clone()).collect();
+        result_list.push(eval_func(lambda.clone(), &mut args)?);
     }
     Ok(MalType::list(result_list))
 }
 
+/// `(mapcat f & colls)` concatenates the collections returned by `map`.
+fn mapcat(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let mapped = map(args, env)?;
+    let mut result = vec![];
+    for item in mapped.list_val().unwrap() {
+        result.extend(seq_result(item)?);
+    }
+    Ok(MalType::list(result))
+}
+
+fn call_pred(pred: &MalType, item: &MalType) -> Result<bool, MalError> {
+    Ok(!eval_func(pred.clone(), &mut vec![item.clone()])?.is_falsey())
+}
+
+/// Collects the items for which `pred` returns `keep`.
+fn filter_items(args: &[MalType], keep: bool) -> MalResult {
+    let mut result = vec![];
+    for item in seq_result(&args[1])? {
+        if call_pred(&args[0], &item)? == keep {
+            result.push(item);
+        }
+    }
+    Ok(MalType::list(result))
+}
+
+fn filter(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    filter_items(args, true)
+}
+
+fn remove(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    filter_items(args, false)
+}
+
+/// `(reduce f coll)` or `(reduce f init coll)`. Without `init`, the first
+/// item is used, and an empty collection gives `(f)`.
+fn reduce(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let func = args[0].clone();
+    let (acc, items) = if args.len() >= 3 {
+        (Some(args[1].clone()), seq_result(&args[2])?)
+    } else {
+        (None, seq_result(&args[1])?)
+    };
+    let mut items = items.into_iter();
+    let mut acc = match acc.or_else(|| items.next()) {
+        Some(acc) => acc,
+        None => return eval_func(func, &mut vec![]),
+    };
+    for item in items {
+        acc = eval_func(func.clone(), &mut vec![acc, item])?;
+    }
+    Ok(acc)
+}
+
+/// `(some pred coll)` returns the first truthy result of `pred`, or nil.
+fn some(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    for item in seq_result(&args[1])? {
+        let result = eval_func(args[0].clone(), &mut vec![item])?;
+        if !result.is_falsey() {
+            return Ok(result);
+        }
+    }
+    Ok(MalType::nil())
+}
+
+fn is_every(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    for item in seq_result(&args[1])? {
+        if !call_pred(&args[0], &item)? {
+            return Ok(MalType::bool_false());
+        }
+    }
+    Ok(MalType::bool_true())
+}
+
+/// The number of leading items for which `pred` is truthy.
+fn count_while(args: &[MalType]) -> Result<(usize, Vec<MalType>), MalError> {
+    let items = seq_result(&args[1])?;
+    let mut count = 0;
+    while count < items.len() && call_pred(&args[0], &items[count])? {
+        count += 1;
+    }
+    Ok((count, items))
+}
+
+fn take_while(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let (count, mut items) = count_while(args)?;
+    items.truncate(count);
+    Ok(MalType::list(items))
+}
+
+fn drop_while(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let (count, items) = count_while(args)?;
+    Ok(MalType::list(items[count..].to_vec()))
+}
+
+/// `(partition n coll)`, `(partition n step coll)` or
+/// `(partition n step pad coll)`. A short last partition is dropped unless
+/// `pad` is given, in which case it is filled from `pad` as far as possible.
+fn partition(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let size = num_result(&args[0])?;
+    let step = if args.len() >= 3 { num_result(&args[1])? } else { size };
+    if size <= 0 || step <= 0 {
+        return Err(MalError::WrongArguments(format!(
+            "partition size and step must be positive but got {} and {}",
+            size, step
+        )));
+    }
+    let (size, step) = (size as usize, step as usize);
+    let pad = if args.len() >= 4 {
+        Some(seq_result(&args[2])?)
+    } else {
+        None
+    };
+    let items = seq_result(args.last().unwrap())?;
+    let mut result = vec![];
+    let mut start = 0;
+    while start < items.len() {
+        let end = (start + size).min(items.len());
+        let mut part = items[start..end].to_vec();
+        if part.len() < size {
+            if let Some(ref pad) = pad {
+                part.extend(pad.iter().take(size - part.len()).cloned());
+                result.push(MalType::list(part));
+            }
+            break;
+        }
+        result.push(MalType::list(part));
+        start += step;
+    }
+    Ok(MalType::list(result))
+}
+
+/// `(interleave & colls)` takes one item from each collection in turn,
+/// stopping at the end of the shortest.
+fn interleave(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let colls: Result<Vec<Vec<MalType>>, MalError> = args.iter().map(seq_result).collect();
+    let colls = colls?;
+    let len = colls.iter().map(Vec::len).min().unwrap_or(0);
+    let mut result = vec![];
+    for index in 0..len {
+        for coll in &colls {
+            result.push(coll[index].clone());
+        }
+    }
+    Ok(MalType::list(result))
+}
+
+/// Keeps the first occurrence of each item, in order.
+fn distinct(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut seen = BTreeSet::new();
+    let mut result = vec![];
+    for item in seq_result(&args[0])? {
+        if seen.insert(item.clone()) {
+            result.push(item);
+        }
+    }
+    Ok(MalType::list(result))
+}
+
+fn frequencies(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut counts: BTreeMap<MalType, i64> = BTreeMap::new();
+    for item in seq_result(&args[0])? {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+    Ok(MalType::hashmap(
+        counts
+            .into_iter()
+            .map(|(item, count)| (item, MalType::number(count)))
+            .collect(),
+    ))
+}
+
+/// `(group-by f coll)` maps each result of `f` to a vector of the items that
+/// produced it.
+fn group_by(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut groups: BTreeMap<MalType, Vec<MalType>> = BTreeMap::new();
+    for item in seq_result(&args[1])? {
+        let key = eval_func(args[0].clone(), &mut vec![item.clone()])?;
+        groups.entry(key).or_default().push(item);
+    }
+    Ok(MalType::hashmap(
+        groups
+            .into_iter()
+            .map(|(key, items)| (key, MalType::vector(items)))
+            .collect(),
+    ))
+}
+
+/// Orders numbers numerically, strings, keywords, symbols and chars
+/// lexically, and lists and vectors element by element. Values of different
+/// kinds cannot be compared, except that nil comes first.
+fn compare(a: &MalType, b: &MalType) -> Result<Ordering, MalError> {
+    if let (Some(a), Some(b)) = (a.number_val(), b.number_val()) {
+        Ok(a.cmp(&b))
+    } else if let (Some(a), Some(b)) = (a.string_val(), b.string_val()) {
+        Ok(a.cmp(b))
+    } else if let (Some(a), Some(b)) = (a.keyword_val(), b.keyword_val()) {
+        Ok(a.cmp(b))
+    } else if let (Some(a), Some(b)) = (a.symbol_val(), b.symbol_val()) {
+        Ok(a.cmp(b))
+    } else if let (Some(a), Some(b)) = (a.char_val(), b.char_val()) {
+        Ok(a.cmp(&b))
+    } else if let (Some(a), Some(b)) = (a.list_or_vector_val(), b.list_or_vector_val()) {
+        for (a, b) in a.iter().zip(b) {
+            let ordering = compare(a, b)?;
+            if ordering != Ordering::Equal {
+                return Ok(ordering);
+            }
+        }
+        Ok(a.len().cmp(&b.len()))
+    } else if a.is_nil() || b.is_nil() {
+        Ok(b.is_nil().cmp(&a.is_nil()))
+    } else if (a.is_true() || a.is_false()) && (b.is_true() || b.is_false()) {
+        Ok(a.is_true().cmp(&b.is_true()))
+    } else {
+        Err(MalError::WrongArguments(format!(
+            "Cannot compare {} with {}",
+            pr_str(a, true),
+            pr_str(b, true)
+        )))
+    }
+}
+
+/// Calls a comparator, which returns a number like `compare` in Clojure or
+/// true when its first argument comes first.
+fn call_comparator(func: &MalType, a: &MalType, b: &MalType) -> Result<Ordering, MalError> {
+    let result = eval_func(func.clone(), &mut vec![a.clone(), b.clone()])?;
+    if let Some(number) = result.number_val() {
+        Ok(number.cmp(&0))
+    } else if !result.is_falsey() {
+        Ok(Ordering::Less)
+    } else if !eval_func(func.clone(), &mut vec![b.clone(), a.clone()])?.is_falsey() {
+        Ok(Ordering::Greater)
+    } else {
+        Ok(Ordering::Equal)
+    }
+}
+
+/// Stable merge sort that stops at the first comparison error. A comparator
+/// that is not a total order gives some order rather than a panic, which
+/// `sort_by` on slices does not promise.
+fn merge_sort<T, F>(mut items: Vec<T>, cmp: &mut F) -> Result<Vec<T>, MalError>
+where
+    F: FnMut(&T, &T) -> Result<Ordering, MalError>,
+{
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = merge_sort(items, cmp)?.into_iter().peekable();
+    let mut right = merge_sort(right, cmp)?.into_iter().peekable();
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        let take_right = cmp(a, b)? == Ordering::Greater;
+        merged.push(if take_right { right.next() } else { left.next() }.unwrap());
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Stable sort of `(key, item)` pairs, returning the first comparison error.
+fn sort_keyed(pairs: Vec<(MalType, MalType)>, comparator: Option<&MalType>) -> MalResult {
+    let sorted = merge_sort(pairs, &mut |a, b| match comparator {
+        Some(func) => call_comparator(func, &a.0, &b.0),
+        None => compare(&a.0, &b.0),
+    })?;
+    Ok(MalType::list(sorted.into_iter().map(|(_, item)| item).collect()))
+}
+
+/// `(sort coll)` or `(sort comparator coll)`
+fn sort(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let items = seq_result(args.last().unwrap())?;
+    let comparator = if args.len() >= 2 { args.first() } else { None };
+    sort_keyed(
+        items.into_iter().map(|item| (item.clone(), item)).collect(),
+        comparator,
+    )
+}
+
+/// `(sort-by keyfn coll)` or `(sort-by keyfn comparator coll)`
+fn sort_by(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut pairs = vec![];
+    for item in seq_result(args.last().unwrap())? {
+        pairs.push((eval_func(args[0].clone(), &mut vec![item.clone()])?, item));
+    }
+    let comparator = if args.len() >= 3 { Some(&args[1]) } else { None };
+    sort_keyed(pairs, comparator)
+}
+
+fn reverse(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut items = seq_result(&args[0])?;
+    items.reverse();
+    Ok(MalType::list(items))
+}
+
 fn is_nil(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "nil?")?;
     Ok(mal_bool(args[0].is_nil()))
 }
 
 fn is_true(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "true?")?;
     Ok(mal_bool(args[0].is_true()))
 }
 
 fn is_false(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "false?")?;
     Ok(mal_bool(args[0].is_false()))
 }
 
 fn symbol(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "symbol")?;
-    if let Some(name) = args[0].string_val() {
-        Ok(MalType::symbol(name.to_owned()))
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass a string to symbol".to_string(),
-        ))
-    }
+    Ok(MalType::symbol(string_arg(args, 0, "symbol")?))
 }
 
 fn is_symbol(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "symbol?")?;
     Ok(mal_bool(args[0].is_symbol()))
 }
 
 fn keyword(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "keyword")?;
-    if let Some(name) = args[0].string_val() {
-        Ok(MalType::keyword(name.to_owned()))
-    } else if args[0].is_keyword() {
-        Ok(args[0].clone())
-    } else {
-        Err(MalError::WrongArguments(
-            "Must pass a string to keyword".to_string(),
-        ))
+    match args[0].string_val() {
+        Some(name) => Ok(MalType::keyword(name.to_owned())),
+        None => Ok(args[0].clone()),
     }
 }
 
 fn is_keyword(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "keyword?")?;
     Ok(mal_bool(args[0].is_keyword()))
 }
 
@@ -540,142 +1272,366 @@ fn hash_map(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn is_map(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "map?")?;
     Ok(mal_bool(args[0].is_hashmap()))
 }
 
+fn set(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(MalType::set(seq_result(&args[0])?.into_iter().collect()))
+}
+
+fn hash_set(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(MalType::set(args.iter().cloned().collect()))
+}
+
+fn is_set(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(mal_bool(args[0].is_set()))
+}
+
+fn disj(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut set = args[0].set_val().unwrap().clone();
+    for item in args.iter().skip(1) {
+        set.remove(item);
+    }
+    Ok(MalType::set_with_meta(
+        set,
+        args[0]
+            .get_metadata()
+            .expect("Expected set to return metadata")
+            .clone(),
+    ))
+}
+
 fn assoc(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    if args.len() % 2 == 1 {
-        if let Some(map) = args[0].hashmap_val() {
-            let mut map = map.clone();
-            let mut list_iter = args.iter().skip(1);
-            loop {
-                if let Some(key) = list_iter.next() {
-                    let val = list_iter.next().unwrap();
-                    map.insert(key.clone(), val.clone());
-                } else {
-                    break;
-                }
-            }
-            let map = MalType::hashmap_with_meta(
-                map,
-                args[0]
-                    .get_metadata()
-                    .expect("Expected hashmap to return metadata")
-                    .clone(),
-            );
-            Ok(map)
-        } else {
-            Err(MalError::WrongArguments(
-                "First argument must be a hash-map".to_string(),
-            ))
-        }
-    } else {
-        Err(MalError::WrongArguments(
+    if args.len() % 2 == 0 {
+        return Err(MalError::WrongArguments(
             "Must pass an odd number of arguments to assoc".to_string(),
-        ))
+        ));
+    }
+    let mut map = args[0].hashmap_val().unwrap().clone();
+    let mut list_iter = args.iter().skip(1);
+    while let Some(key) = list_iter.next() {
+        let val = list_iter.next().unwrap();
+        map.insert(key.clone(), val.clone());
     }
+    Ok(MalType::hashmap_with_meta(
+        map,
+        args[0]
+            .get_metadata()
+            .expect("Expected hashmap to return metadata")
+            .clone(),
+    ))
 }
 
 fn dissoc(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "dissoc")?;
+    let mut map = args[0].hashmap_val().unwrap().clone();
+    for key in args.iter().skip(1) {
+        map.remove(key);
+    }
+    Ok(MalType::hashmap_with_meta(
+        map,
+        args[0]
+            .get_metadata()
+            .expect("Expected hashmap to return metadata")
+            .clone(),
+    ))
+}
+
+fn get(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let found = if let Some(map) = args[0].hashmap_val() {
+        map.get(&args[1])
+    } else if let Some(set) = args[0].set_val() {
+        set.get(&args[1])
+    } else {
+        None
+    };
+    Ok(found.cloned().unwrap_or_else(MalType::nil))
+}
+
+fn contains(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     if let Some(map) = args[0].hashmap_val() {
-        let mut map = map.clone();
-        let mut list_iter = args.iter();
-        loop {
-            if let Some(key) = list_iter.next() {
-                if map.contains_key(key) {
-                    map.remove(key);
-                }
-            } else {
-                break;
-            }
+        Ok(mal_bool(map.contains_key(&args[1])))
+    } else if let Some(set) = args[0].set_val() {
+        Ok(mal_bool(set.contains(&args[1])))
+    } else {
+        Ok(MalType::bool_false())
+    }
+}
+
+fn keys(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let map = args[0].hashmap_val().unwrap();
+    Ok(MalType::list(map.keys().cloned().collect()))
+}
+
+fn vals(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let map = args[0].hashmap_val().unwrap();
+    Ok(MalType::list(map.values().cloned().collect()))
+}
+
+/// Looks up `key` in a map, set or vector, or nil. `Ok(None)` means the key
+/// is missing; looking inside anything else is an error.
+fn lookup(coll: &MalType, key: &MalType) -> Result<Option<MalType>, ()> {
+    if coll.is_nil() {
+        Ok(None)
+    } else if let Some(map) = coll.hashmap_val() {
+        Ok(map.get(key).cloned())
+    } else if let Some(set) = coll.set_val() {
+        Ok(set.get(key).cloned())
+    } else if let (Some(vec), Some(index)) = (coll.vector_val(), key.number_val()) {
+        if index < 0 {
+            Ok(None)
+        } else {
+            Ok(vec.get(index as usize).cloned())
         }
-        let map = MalType::hashmap_with_meta(
-            map,
-            args[0]
-                .get_metadata()
-                .expect("Expected hashmap to return metadata")
-                .clone(),
-        );
-        Ok(map)
     } else {
-        Err(MalError::WrongArguments(
-            "First argument must be a hash-map".to_string(),
-        ))
+        Err(())
     }
 }
 
-fn get(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "get")?;
-    if let Some(map) = args[0].hashmap_val() {
-        let key = &args[1];
-        match map.get(key) {
-            Some(val) => Ok(val.clone()),
-            None => Ok(MalType::nil()),
+/// Sets `key` in a map or vector, or in a new map if `coll` is nil. A vector
+/// index may be one past the end to append.
+fn assoc_one(coll: &MalType, key: MalType, val: MalType) -> Result<MalType, ()> {
+    if coll.is_nil() {
+        let mut map = BTreeMap::new();
+        map.insert(key, val);
+        Ok(MalType::hashmap(map))
+    } else if let Some(map) = coll.hashmap_val() {
+        let mut map = map.clone();
+        map.insert(key, val);
+        Ok(MalType::hashmap_with_meta(map, coll.get_metadata().unwrap().clone()))
+    } else if let (Some(vec), Some(index)) = (coll.vector_val(), key.number_val()) {
+        let mut vec = vec.clone();
+        if index >= 0 && (index as usize) < vec.len() {
+            vec[index as usize] = val;
+        } else if index >= 0 && index as usize == vec.len() {
+            vec.push(val);
+        } else {
+            return Err(());
         }
-    } else if args[0].is_nil() {
-        Ok(MalType::nil())
+        Ok(MalType::vector_with_meta(vec, coll.get_metadata().unwrap().clone()))
     } else {
-        Err(MalError::WrongArguments(
-            "First argument must be a hash-map".to_string(),
-        ))
+        Err(())
     }
 }
 
-fn contains(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "contains")?;
-    if let Some(map) = args[0].hashmap_val() {
-        let key = &args[1];
-        Ok(mal_bool(map.contains_key(key)))
+fn path_error(name: &str, path: &[MalType], index: usize, coll: &MalType) -> MalError {
+    MalError::WrongArguments(format!(
+        "{} cannot use path element {} ({}) on {}",
+        name,
+        index + 1,
+        pr_str(&path[index], true),
+        pr_str(coll, true)
+    ))
+}
+
+fn path_arg(
+    args: &[MalType],
+    index: usize,
+    name: &str,
+    allow_empty: bool,
+) -> Result<Vec<MalType>, MalError> {
+    let path = args[index].list_or_vector_val().unwrap();
+    if path.is_empty() && !allow_empty {
+        return Err(MalError::WrongArguments(format!(
+            "Must pass a non-empty path to {}",
+            name
+        )));
+    }
+    Ok(path.clone())
+}
+
+/// `(get-in coll path)` or `(get-in coll path default)`
+fn get_in(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = path_arg(args, 1, "get-in", true)?;
+    let mut current = args[0].clone();
+    for index in 0..path.len() {
+        match lookup(&current, &path[index]) {
+            Ok(Some(val)) => current = val,
+            Ok(None) => return Ok(args.get(2).cloned().unwrap_or_else(MalType::nil)),
+            Err(()) => return Err(path_error("get-in", &path, index, &current)),
+        }
+    }
+    Ok(current)
+}
+
+/// Replaces the value at `path` inside `coll` with `f` of the old value,
+/// creating maps for missing levels.
+fn update_path<F>(name: &str, coll: &MalType, path: &[MalType], index: usize, f: &F) -> MalResult
+where
+    F: Fn(MalType) -> MalResult,
+{
+    let old = lookup(coll, &path[index]).map_err(|_| path_error(name, path, index, coll))?;
+    let old = old.unwrap_or_else(MalType::nil);
+    let new = if index + 1 == path.len() {
+        f(old)?
     } else {
-        Err(MalError::WrongArguments(
-            "First argument must be a hash-map".to_string(),
-        ))
+        update_path(name, &old, path, index + 1, f)?
+    };
+    assoc_one(coll, path[index].clone(), new).map_err(|_| path_error(name, path, index, coll))
+}
+
+fn assoc_in(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = path_arg(args, 1, "assoc-in", false)?;
+    let val = args[2].clone();
+    update_path("assoc-in", &args[0], &path, 0, &|_| Ok(val.clone()))
+}
+
+/// `(update coll key f & args)` calls `(f old-value & args)`.
+fn update(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = vec![args[1].clone()];
+    let func = args[2].clone();
+    let extra = args[3..].to_vec();
+    update_path("update", &args[0], &path, 0, &|old| {
+        let mut func_args = vec![old];
+        func_args.extend(extra.iter().cloned());
+        eval_func(func.clone(), &mut func_args)
+    })
+}
+
+fn update_in(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = path_arg(args, 1, "update-in", false)?;
+    let func = args[2].clone();
+    let extra = args[3..].to_vec();
+    update_path("update-in", &args[0], &path, 0, &|old| {
+        let mut func_args = vec![old];
+        func_args.extend(extra.iter().cloned());
+        eval_func(func.clone(), &mut func_args)
+    })
+}
+
+/// Merges maps left to right, calling `resolve` with both values when a key
+/// is already present. Nil arguments are skipped, and all nils give nil.
+fn merge_maps<F>(maps: &[MalType], resolve: F) -> MalResult
+where
+    F: Fn(MalType, MalType) -> MalResult,
+{
+    let mut result: Option<MalType> = None;
+    for map in maps {
+        let entries = match map.hashmap_val() {
+            Some(entries) => entries,
+            None => continue,
+        };
+        result = Some(match result {
+            None => map.clone(),
+            Some(acc) => {
+                let mut merged = acc.hashmap_val().unwrap().clone();
+                for (key, val) in entries {
+                    let val = match merged.remove(key) {
+                        Some(old) => resolve(old, val.clone())?,
+                        None => val.clone(),
+                    };
+                    merged.insert(key.clone(), val);
+                }
+                MalType::hashmap_with_meta(merged, acc.get_metadata().unwrap().clone())
+            }
+        });
     }
+    Ok(result.unwrap_or_else(MalType::nil))
 }
 
-fn keys(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "keys")?;
-    if let Some(map) = args[0].hashmap_val() {
-        let list = map.keys().map(|k| k.clone()).collect();
+fn merge(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    merge_maps(args, |_, new| Ok(new))
+}
+
+/// `(merge-with f & maps)` combines values for the same key with `(f old new)`.
+fn merge_with(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let func = args[0].clone();
+    merge_maps(&args[1..], |old, new| {
+        eval_func(func.clone(), &mut vec![old, new])
+    })
+}
+
+fn select_keys(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut map = BTreeMap::new();
+    for key in seq_result(&args[1])? {
+        match lookup(&args[0], &key) {
+            Ok(Some(val)) => {
+                map.insert(key, val);
+            }
+            Ok(None) => {}
+            Err(()) => {
+                return Err(MalError::WrongArguments(format!(
+                    "Cannot select key {} from {}",
+                    pr_str(&key, true),
+                    pr_str(&args[0], true)
+                )))
+            }
+        }
+    }
+    Ok(MalType::hashmap(map))
+}
+
+/// `(zipmap keys vals)` pairs keys with values up to the shorter of the two.
+fn zipmap(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let keys = seq_result(&args[0])?;
+    let vals = seq_result(&args[1])?;
+    Ok(MalType::hashmap(keys.into_iter().zip(vals).collect()))
+}
+
+/// `(into to from)` adds each item of `from` to `to` as `conj` would. Items
+/// added to a map must be `[key value]` pairs.
+fn into(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let items = seq_result(&args[1])?;
+    let to = &args[0];
+    if to.is_nil() || to.is_list() {
+        // Each item goes on the front, so they end up in reverse.
+        let mut list: Vec<MalType> = items.into_iter().rev().collect();
+        list.extend(seq_result(to)?);
         Ok(MalType::list(list))
+    } else if let Some(map) = to.hashmap_val() {
+        let mut map = map.clone();
+        for item in items {
+            match item.list_or_vector_val() {
+                Some(pair) if pair.len() == 2 => {
+                    map.insert(pair[0].clone(), pair[1].clone());
+                }
+                _ => {
+                    return Err(MalError::WrongArguments(format!(
+                        "into a map expects [key value] pairs but got: {}",
+                        pr_str(&item, true)
+                    )))
+                }
+            }
+        }
+        Ok(MalType::hashmap_with_meta(map, to.get_metadata().unwrap().clone()))
     } else {
-        Err(MalError::WrongArguments(
-            "First argument must be a hash-map".to_string(),
-        ))
+        let mut conj_args = vec![to.clone()];
+        conj_args.extend(items);
+        if conj_args.len() == 1 {
+            return Ok(to.clone());
+        }
+        conj(&mut conj_args, None)
     }
 }
 
-fn vals(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "vals")?;
-    if let Some(map) = args[0].hashmap_val() {
-        let list = map.values().map(|k| k.clone()).collect();
-        Ok(MalType::list(list))
+/// `(reduce-kv f init coll)` calls `(f acc key value)` for each map entry,
+/// or `(f acc index item)` for a vector.
+fn reduce_kv(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let func = args[0].clone();
+    let mut acc = args[1].clone();
+    let entries: Vec<(MalType, MalType)> = if let Some(map) = args[2].hashmap_val() {
+        map.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    } else if let Some(vec) = args[2].vector_val() {
+        vec.iter()
+            .enumerate()
+            .map(|(i, v)| (MalType::number(i as i64), v.clone()))
+            .collect()
+    } else if args[2].is_nil() {
+        vec![]
     } else {
-        Err(MalError::WrongArguments(
-            "First argument must be a hash-map".to_string(),
-        ))
+        return Err(arg_error("reduce-kv", "map, vector or nil", 2, &args[2]));
+    };
+    for (key, val) in entries {
+        acc = eval_func(func.clone(), &mut vec![acc, key, val])?;
     }
+    Ok(acc)
 }
 
 fn is_sequential(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "sequential?")?;
     Ok(mal_bool(args[0].is_list_or_vector()))
 }
 
 fn readline(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    let prompt = if args.len() >= 1 {
-        if let Some(string) = args[0].string_val() {
-            string
-        } else {
-            return Err(MalError::WrongArguments(
-                "Must pass a string to readline".to_string(),
-            ));
-        }
-    } else {
-        ">"
-    };
+    let prompt = args.first().and_then(|arg| arg.string_val()).unwrap_or(">");
     let mut readline = Readline::new(prompt);
     match readline.get() {
         Some(line) => Ok(MalType::string(line)),
@@ -684,7 +1640,6 @@ fn readline(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn meta(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "meta")?;
     if let Some(metadata) = args[0].get_metadata() {
         Ok(metadata.to_owned())
     } else {
@@ -693,23 +1648,47 @@ fn meta(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn with_meta(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "with-meta")?;
     let mut func = args[0].clone();
     Ok(func.clone_with_meta(args[1].clone()))
 }
 
 fn is_string(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "string?")?;
     Ok(mal_bool(args[0].is_string()))
 }
 
 fn is_number(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "number?")?;
     Ok(mal_bool(args[0].is_number()))
 }
 
+fn char_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    match args[0].number_val() {
+        Some(num) => char_from_number(num).map(MalType::char).ok_or_else(|| {
+            MalError::WrongArguments(format!("{} is not a valid character code", num))
+        }),
+        None => Ok(args[0].clone()),
+    }
+}
+
+fn char_from_number(num: i64) -> Option<char> {
+    if (0..=i64::from(u32::MAX)).contains(&num) {
+        ::std::char::from_u32(num as u32)
+    } else {
+        None
+    }
+}
+
+fn is_char(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(mal_bool(args[0].is_char()))
+}
+
+fn int(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    match args[0].char_val() {
+        Some(c) => Ok(MalType::number(c as i64)),
+        None => Ok(args[0].clone()),
+    }
+}
+
 fn is_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "fn?")?;
     if args[0].is_function() {
         Ok(MalType::bool_true())
     } else if let Some(Lambda { is_macro, .. }) = args[0].lambda_val() {
@@ -720,7 +1699,6 @@ fn is_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
 }
 
 fn is_macro(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "macro?")?;
     if let Some(Lambda { is_macro, .. }) = args[0].lambda_val() {
         Ok(mal_bool(*is_macro))
     } else {
@@ -728,55 +1706,416 @@ fn is_macro(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     }
 }
 
+/// Lists and nil get the items at the front, vectors at the end, and maps
+/// take `[key value]` pairs.
 fn conj(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 2, "conj")?;
-    if let Some(vec) = args[0].list_val() {
-        let mut vec = vec.to_owned();
-        for new_item in args.iter().skip(1) {
-            vec.insert(0, new_item.clone());
-        }
-        Ok(MalType::list(vec))
+    let items = args.iter().skip(1).cloned();
+    if args[0].is_nil() || args[0].is_list() {
+        let mut list: Vec<MalType> = items.rev().collect();
+        list.extend(seq_result(&args[0])?);
+        Ok(MalType::list(list))
     } else if let Some(vec) = args[0].vector_val() {
-        let mut vec = vec.to_owned();
-        for new_item in args.iter().skip(1) {
-            vec.push(new_item.clone());
-        }
+        let mut vec = vec.clone();
+        vec.extend(items);
         Ok(MalType::vector(vec))
+    } else if let Some(set) = args[0].set_val() {
+        let mut set = set.clone();
+        set.extend(items);
+        Ok(MalType::set(set))
     } else {
-        Err(MalError::WrongArguments(
-            "Must pass a list or vector to conj".to_string(),
-        ))
+        let mut map = args[0].hashmap_val().unwrap().clone();
+        for (index, item) in args.iter().enumerate().skip(1) {
+            match item.list_or_vector_val() {
+                Some(pair) if pair.len() == 2 => {
+                    map.insert(pair[0].clone(), pair[1].clone());
+                }
+                _ => return Err(arg_error("conj", "[key value] pair", index, item)),
+            }
+        }
+        Ok(MalType::hashmap(map))
     }
 }
 
 fn seq(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
-    assert_arg_count_gte(args, 1, "seq")?;
-    if let Some(string) = args[0].string_val() {
-        if string.len() == 0 {
-            Ok(MalType::nil())
-        } else {
-            Ok(MalType::list(
-                string
-                    .chars()
-                    .map(|c| MalType::string(c.to_string()))
-                    .collect(),
-            ))
+    let items = match args[0].string_val() {
+        Some(string) => string.chars().map(MalType::char).collect(),
+        None => seq_result(&args[0])?,
+    };
+    if items.is_empty() {
+        Ok(MalType::nil())
+    } else {
+        Ok(MalType::list(items))
+    }
+}
+
+/// Converts a character index into a byte offset, allowing the index one
+/// past the last character.
+fn char_to_byte_index(string: &str, index: i64) -> Result<usize, MalError> {
+    if index < 0 {
+        return Err(MalError::WrongArguments(format!(
+            "String index must not be negative but got: {}",
+            index
+        )));
+    }
+    let size = string.chars().count();
+    if index as usize > size {
+        return Err(MalError::IndexOutOfBounds {
+            size,
+            index: index as usize,
+        });
+    }
+    Ok(string
+        .char_indices()
+        .nth(index as usize)
+        .map(|(byte, _)| byte)
+        .unwrap_or_else(|| string.len()))
+}
+
+fn subs(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "subs")?;
+    let start = char_to_byte_index(string, num_result(&args[1])?)?;
+    let end = if args.len() > 2 {
+        char_to_byte_index(string, num_result(&args[2])?)?
+    } else {
+        string.len()
+    };
+    if start > end {
+        return Err(MalError::WrongArguments(
+            "Start index must not be greater than end index in subs".to_string(),
+        ));
+    }
+    Ok(MalType::string(&string[start..end]))
+}
+
+fn split(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "split")?;
+    if let Some(regex) = args[1].regex_val() {
+        return Ok(MalType::vector(
+            regex.regex.split(string).map(MalType::string).collect(),
+        ));
+    }
+    let separator = string_arg(args, 1, "split")?;
+    if separator.is_empty() {
+        return Ok(MalType::vector(
+            string.chars().map(|c| MalType::string(c.to_string())).collect(),
+        ));
+    }
+    Ok(MalType::vector(
+        string.split(separator).map(MalType::string).collect(),
+    ))
+}
+
+fn join(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let (separator, list) = if args.len() > 1 {
+        (string_arg(args, 0, "join")?, &args[1])
+    } else {
+        ("", &args[0])
+    };
+    let items = seq_result(list)?;
+    let results: Vec<String> = items.iter().map(|item| pr_str(item, false)).collect();
+    Ok(MalType::string(results.join(separator)))
+}
+
+fn upper_case(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(MalType::string(string_arg(args, 0, "upper-case")?.to_uppercase()))
+}
+
+fn lower_case(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(MalType::string(string_arg(args, 0, "lower-case")?.to_lowercase()))
+}
+
+fn trim(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(MalType::string(string_arg(args, 0, "trim")?.trim()))
+}
+
+/// Replaces every match of a string or regex. With a regex, the replacement
+/// may refer to groups as `$1`, or be a function called with each match.
+fn replace(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "replace")?;
+    if let Some(regex) = args[1].regex_val() {
+        let replacement = &args[2];
+        if replacement.string_val().is_none()
+            && !replacement.is_function()
+            && !replacement.is_lambda()
+        {
+            return Err(arg_error("replace", "string or function", 2, replacement));
+        }
+        let mut result = String::new();
+        let mut last = 0;
+        for caps in regex.regex.captures_iter(string) {
+            let whole = caps.get(0).unwrap();
+            result.push_str(&string[last..whole.start()]);
+            if let Some(template) = replacement.string_val() {
+                caps.expand(template, &mut result);
+            } else {
+                let value = eval_func(replacement.clone(), &mut vec![match_result(&caps)])?;
+                result.push_str(&pr_str(&value, false));
+            }
+            last = whole.end();
+        }
+        result.push_str(&string[last..]);
+        return Ok(MalType::string(result));
+    }
+    let pattern = string_arg(args, 1, "replace")?;
+    let replacement = string_arg(args, 2, "replace")?;
+    Ok(MalType::string(string.replace(pattern, replacement)))
+}
+
+fn starts_with(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "starts-with?")?;
+    Ok(mal_bool(string.starts_with(string_arg(args, 1, "starts-with?")?)))
+}
+
+fn ends_with(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "ends-with?")?;
+    Ok(mal_bool(string.ends_with(string_arg(args, 1, "ends-with?")?)))
+}
+
+fn index_of(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "index-of")?;
+    let needle = string_arg(args, 1, "index-of")?;
+    let from = if args.len() > 2 {
+        char_to_byte_index(string, num_result(&args[2])?)?
+    } else {
+        0
+    };
+    match string[from..].find(needle) {
+        Some(byte) => Ok(MalType::number(
+            string[..from + byte].chars().count() as i64,
+        )),
+        None => Ok(MalType::nil()),
+    }
+}
+
+fn includes(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let string = string_arg(args, 0, "includes?")?;
+    Ok(mal_bool(string.contains(string_arg(args, 1, "includes?")?)))
+}
+
+fn is_blank(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    if args[0].is_nil() {
+        return Ok(MalType::bool_true());
+    }
+    let string = string_arg(args, 0, "blank?")?;
+    Ok(mal_bool(string.trim().is_empty()))
+}
+
+/// The largest width or precision `format` accepts.
+const FORMAT_MAX_COUNT: usize = 10_000;
+
+/// Reads the digits of a width or precision in a `format` directive.
+fn format_count(chars: &mut Peekable<Chars>, what: &str) -> Result<usize, MalError> {
+    let mut count: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        count = count
+            .checked_mul(10)
+            .and_then(|count| count.checked_add(digit as usize))
+            .filter(|&count| count <= FORMAT_MAX_COUNT)
+            .ok_or_else(|| {
+                MalError::WrongArguments(format!(
+                    "Format {} must be at most {}",
+                    what, FORMAT_MAX_COUNT
+                ))
+            })?;
+        chars.next();
+    }
+    Ok(count)
+}
+
+/// printf-style formatting. Supports the `-`, `0`, `+` and space flags, a
+/// width, a precision (for `%s`), and the directives `%s %d %x %X %o %b %c
+/// %n %%`.
+fn format_fn(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let template = string_arg(args, 0, "format")?;
+    let mut values = args.iter().skip(1);
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let (mut left, mut zero, mut plus, mut space) = (false, false, false, false);
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => left = true,
+                '0' => zero = true,
+                '+' => plus = true,
+                ' ' => space = true,
+                _ => break,
+            }
+            chars.next();
         }
-    } else if let Some(vec) = args[0].list_or_vector_val() {
-        if vec.len() == 0 {
-            Ok(MalType::nil())
+        let width = format_count(&mut chars, "width")?;
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            precision = Some(format_count(&mut chars, "precision")?);
+        }
+        let directive = chars.next().ok_or_else(|| {
+            MalError::WrongArguments("Incomplete directive at end of format string".to_string())
+        })?;
+        if directive == '%' {
+            out.push('%');
+            continue;
+        } else if directive == 'n' {
+            out.push('\n');
+            continue;
+        }
+        let value = values.next().ok_or_else(|| {
+            MalError::WrongArguments(format!(
+                "Not enough arguments to format for directive %{}",
+                directive
+            ))
+        })?;
+        let (sign, body) = match directive {
+            's' => {
+                let string = pr_str(value, false);
+                match precision {
+                    Some(p) => ("", string.chars().take(p).collect()),
+                    None => ("", string),
+                }
+            }
+            'c' => match value.char_val().or_else(|| value.number_val().and_then(char_from_number)) {
+                Some(c) => ("", c.to_string()),
+                None => {
+                    return Err(MalError::WrongArguments(format!(
+                        "Expected a char for %c in format but got: {:?}",
+                        value
+                    )))
+                }
+            },
+            'd' | 'x' | 'X' | 'o' | 'b' => {
+                let num = value.number_val().ok_or_else(|| {
+                    MalError::WrongArguments(format!(
+                        "Expected a number for %{} in format but got: {:?}",
+                        directive, value
+                    ))
+                })?;
+                let sign = if num < 0 {
+                    "-"
+                } else if plus {
+                    "+"
+                } else if space {
+                    " "
+                } else {
+                    ""
+                };
+                let magnitude = (num as i128).abs();
+                let body = match directive {
+                    'x' => format!("{:x}", magnitude),
+                    'X' => format!("{:X}", magnitude),
+                    'o' => format!("{:o}", magnitude),
+                    'b' => format!("{:b}", magnitude),
+                    _ => magnitude.to_string(),
+                };
+                (sign, body)
+            }
+            _ => {
+                return Err(MalError::WrongArguments(format!(
+                    "Unknown format directive: %{}",
+                    directive
+                )))
+            }
+        };
+        let len = sign.chars().count() + body.chars().count();
+        let padding = width.saturating_sub(len);
+        if left {
+            out.push_str(sign);
+            out.push_str(&body);
+            out.push_str(&" ".repeat(padding));
+        } else if zero && directive != 's' && directive != 'c' {
+            out.push_str(sign);
+            out.push_str(&"0".repeat(padding));
+            out.push_str(&body);
         } else {
-            Ok(MalType::list(vec.clone()))
+            out.push_str(&" ".repeat(padding));
+            out.push_str(sign);
+            out.push_str(&body);
         }
-    } else if args[0].is_nil() {
+    }
+    Ok(MalType::string(out))
+}
+
+/// A match is returned as a string, or as a vector of the whole match and
+/// its groups when the regex has groups.
+fn match_result(caps: &Captures) -> MalType {
+    if caps.len() == 1 {
+        MalType::string(caps.get(0).unwrap().as_str())
+    } else {
+        groups_result(caps)
+    }
+}
+
+fn groups_result(caps: &Captures) -> MalType {
+    MalType::vector(
+        caps.iter()
+            .map(|group| match group {
+                Some(group) => MalType::string(group.as_str()),
+                None => MalType::nil(),
+            })
+            .collect(),
+    )
+}
+
+fn is_regex(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(mal_bool(args[0].is_regex()))
+}
+
+fn re_pattern(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    match args[0].string_val() {
+        Some(pattern) => Ok(MalType::regex(MalRegex::new(pattern)?)),
+        None => Ok(args[0].clone()),
+    }
+}
+
+fn re_find(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let regex = args[0].regex_val().unwrap();
+    let string = string_arg(args, 1, "re-find")?;
+    Ok(regex
+        .regex
+        .captures(string)
+        .map(|caps| match_result(&caps))
+        .unwrap_or_else(MalType::nil))
+}
+
+fn re_matches(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let regex = args[0].regex_val().unwrap();
+    let string = string_arg(args, 1, "re-matches")?;
+    Ok(regex
+        .anchored
+        .captures(string)
+        .map(|caps| match_result(&caps))
+        .unwrap_or_else(MalType::nil))
+}
+
+fn re_seq(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let regex = args[0].regex_val().unwrap();
+    let string = string_arg(args, 1, "re-seq")?;
+    let matches: Vec<MalType> = regex
+        .regex
+        .captures_iter(string)
+        .map(|caps| match_result(&caps))
+        .collect();
+    if matches.is_empty() {
         Ok(MalType::nil())
     } else {
-        Err(MalError::WrongArguments(
-            "Must pass a string, list, or vector to seq".to_string(),
-        ))
+        Ok(MalType::list(matches))
     }
 }
 
+/// Like `re-find`, but always returns a vector of the whole match followed
+/// by every group, even when the regex has no groups.
+fn re_groups(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let regex = args[0].regex_val().unwrap();
+    let string = string_arg(args, 1, "re-groups")?;
+    Ok(regex
+        .regex
+        .captures(string)
+        .map(|caps| groups_result(&caps))
+        .unwrap_or_else(MalType::nil))
+}
+
 fn gensym(_args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
     let env = env.expect("env must be passed to gensym");
     let mut auto_incr = env.get("*gensym-auto-incr*").unwrap();
@@ -804,6 +2143,177 @@ fn time_ms(_args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
     Ok(MalType::number(ms))
 }
 
+/// The signature of the builtin with this name.
+pub fn builtin_signature(name: &str) -> Option<&'static Signature> {
+    BUILTINS
+        .iter()
+        .map(|builtin| &builtin.signature)
+        .find(|signature| signature.name == name)
+}
+
+/// What `doc` shows for a value: its arglists and docstring.
+struct Doc {
+    arglists: Option<String>,
+    doc: Option<String>,
+    is_macro: bool,
+}
+
+/// Builtins are documented by their signature, found by `name`. Anything
+/// else has the `:doc` from its metadata, and lambdas their parameters.
+fn doc_for(name: Option<&str>, value: &MalType) -> Doc {
+    if value.is_function() {
+        if let Some(signature) = name.and_then(builtin_signature) {
+            return Doc {
+                arglists: Some(format!("({})", signature.arglists)),
+                doc: Some(signature.doc.to_string()),
+                is_macro: false,
+            };
+        }
+    }
+    let doc = value
+        .get_metadata()
+        .and_then(|meta| meta.hashmap_val())
+        .and_then(|meta| meta.get(&MalType::keyword("doc")))
+        .and_then(|doc| doc.string_val())
+        .map(|doc| doc.to_string());
+    match value.lambda_val() {
+        Some(lambda) => Doc {
+            arglists: Some(format!(
+                "({})",
+                pr_str(&MalType::vector(lambda.args.clone()), true)
+            )),
+            doc,
+            is_macro: lambda.is_macro,
+        },
+        None => Doc {
+            arglists: None,
+            doc,
+            is_macro: false,
+        },
+    }
+}
+
+fn doc_text(name: Option<&str>, doc: &Doc) -> String {
+    let mut lines = vec!["-------------------------".to_string()];
+    lines.extend(name.map(|name| name.to_string()));
+    lines.extend(doc.arglists.clone());
+    if doc.is_macro {
+        lines.push("Macro".to_string());
+    }
+    lines.extend(doc.doc.as_ref().map(|doc| format!("  {}", doc)));
+    lines.join("\n")
+}
+
+/// `(doc 'name)` prints the arglists and docstring of what `name` is bound
+/// to. Any other value is documented from its metadata.
+fn doc(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let name = args[0]
+        .symbol_val()
+        .or_else(|| args[0].string_val())
+        .map(|name| name.to_string());
+    let out = env.clone();
+    let value = match (&name, env) {
+        (Some(name), Some(env)) => env.get(name)?,
+        _ => args[0].clone(),
+    };
+    let name = name
+        .as_deref()
+        .or_else(|| value.lambda_val().and_then(|lambda| lambda.name.as_deref()));
+    print_line(&out, &doc_text(name, &doc_for(name, &value)))?;
+    Ok(MalType::nil())
+}
+
+fn lambda_arg<'a>(args: &'a [MalType], name: &str) -> Result<Option<&'a Lambda>, MalError> {
+    if args[0].is_function() {
+        Ok(None)
+    } else if let Some(lambda) = args[0].lambda_val() {
+        Ok(Some(lambda))
+    } else {
+        Err(arg_error(name, "function", 0, &args[0]))
+    }
+}
+
+/// The parameters of a function, or nil for a builtin.
+fn fn_args(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(lambda_arg(args, "fn-args")?
+        .map(|lambda| MalType::vector(lambda.args.clone()))
+        .unwrap_or_else(MalType::nil))
+}
+
+/// The body of a function, or nil for a builtin.
+fn fn_body(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(lambda_arg(args, "fn-body")?
+        .map(|lambda| lambda.body[0].clone())
+        .unwrap_or_else(MalType::nil))
+}
+
+/// `(source 'name)` prints the `def!` or `defmacro!` form that defined
+/// `name`, as it was read. Builtins have no source.
+fn source(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let name = match args[0].symbol_val().or_else(|| args[0].string_val()) {
+        Some(name) => name,
+        None => {
+            return Err(arg_error("source", "symbol", 0, &args[0]))
+        }
+    };
+    let env_ref = env.as_ref().ok_or_else(|| {
+        MalError::WrongArguments("source needs an environment to look in".to_string())
+    })?;
+    env_ref.get(name)?;
+    match env_ref.source(name) {
+        Some(form) => print_line(&env, &pretty_str(&form, &PrettyOptions::default()))?,
+        None => print_line(&env, "Source not found")?,
+    }
+    Ok(MalType::nil())
+}
+
+/// Matches a name or docstring against a string, as a substring, or a regex.
+fn matches_pattern(pattern: &MalType, text: &str) -> bool {
+    match pattern.regex_val() {
+        Some(regex) => regex.regex.is_match(text),
+        None => text.contains(pattern.string_val().unwrap_or("")),
+    }
+}
+
+/// The global names and their values. Builtins are called with the global
+/// environment, so `let*` and function locals are never included.
+fn names(env: Option<Env>, name: &str) -> Result<Vec<(String, MalType)>, MalError> {
+    let env = env.ok_or_else(|| {
+        MalError::WrongArguments(format!("{} needs an environment to search", name))
+    })?;
+    Ok(env.names()
+        .into_iter()
+        .filter_map(|name| env.get(&name).ok().map(|value| (name, value)))
+        .collect())
+}
+
+/// `(find-doc pattern)` prints the docs of every global whose name or
+/// docstring matches.
+fn find_doc(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    for (name, value) in names(env.clone(), "find-doc")? {
+        let doc = doc_for(Some(&name), &value);
+        let doc_matches = match doc.doc {
+            Some(ref text) => matches_pattern(&args[0], text),
+            None => false,
+        };
+        if doc_matches || matches_pattern(&args[0], &name) {
+            print_line(&env, &doc_text(Some(&name), &doc))?;
+        }
+    }
+    Ok(MalType::nil())
+}
+
+/// `(apropos pattern)` returns the global symbols whose names match.
+fn apropos(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    Ok(MalType::list(
+        names(env, "apropos")?
+            .into_iter()
+            .filter(|(name, _)| matches_pattern(&args[0], name))
+            .map(|(name, _)| MalType::symbol(name))
+            .collect(),
+    ))
+}
+
 fn eval(mut args: Vec<MalType>, env: &Env) -> MalResult {
     if let Some(Function { func, .. }) = env.get("eval")
         .expect("eval not a function!")
@@ -827,26 +2337,66 @@ pub fn eval_func(func: MalType, mut args: &mut Vec<MalType>) -> MalResult {
     {
         let inner_env = Env::with_binds(Some(&env), binds.clone(), args.clone());
         return eval(body.clone(), &inner_env);
+    } else if let Some(result) = call_data(&func, args) {
+        return result;
     }
     Err(MalError::NotAFunction(func))
 }
 
-struct MalNumberIter<'a> {
-    items: &'a mut Vec<MalType>,
-}
-
-impl<'a> Iterator for MalNumberIter<'a> {
-    type Item = Result<i64, MalError>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.items.len() == 0 {
-            return None;
-        }
-        let item = self.items.remove(0);
-        if let Some(num) = item.number_val() {
-            Some(Ok(num))
+/// Calls a keyword, map, set or vector as a lookup, as in `(:a m)`, `(m :a)`
+/// or `(v 0)`, with an optional default when nothing is found. Returns `None`
+/// for any other value.
+pub fn call_data(func: &MalType, args: &[MalType]) -> Option<MalResult> {
+    if !(func.is_keyword() || func.is_hashmap() || func.is_set() || func.is_vector()) {
+        return None;
+    }
+    if args.is_empty() || args.len() > 2 {
+        return Some(Err(MalError::WrongArguments(format!(
+            "Wrong number of args ({}) passed to {}",
+            args.len(),
+            pr_str(func, true)
+        ))));
+    }
+    let default = args.get(1).cloned();
+    let found = if func.is_keyword() {
+        if let Some(map) = args[0].hashmap_val() {
+            map.get(func).cloned()
+        } else if let Some(set) = args[0].set_val() {
+            set.get(func).cloned()
         } else {
-            Some(Err(MalError::NotANumber))
+            None
         }
-    }
+    } else if let Some(map) = func.hashmap_val() {
+        map.get(&args[0]).cloned()
+    } else if let Some(set) = func.set_val() {
+        set.get(&args[0]).cloned()
+    } else {
+        let vec = func.vector_val().unwrap();
+        let index = match args[0].number_val() {
+            Some(index) => index,
+            None => {
+                return Some(Err(MalError::WrongArguments(format!(
+                    "Vector index must be a number but got: {:?}",
+                    args[0]
+                ))))
+            }
+        };
+        if index < 0 && default.is_none() {
+            return Some(Err(MalError::WrongArguments(format!(
+                "Vector index must not be negative but got: {}",
+                index
+            ))));
+        }
+        match vec.get(index as usize) {
+            Some(val) if index >= 0 => Some(val.clone()),
+            _ if default.is_none() => {
+                return Some(Err(MalError::IndexOutOfBounds {
+                    size: vec.len(),
+                    index: index as usize,
+                }))
+            }
+            _ => None,
+        }
+    };
+    Some(Ok(found.or(default).unwrap_or_else(MalType::nil)))
 }
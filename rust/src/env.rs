@@ -1,5 +1,5 @@
 use types::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::rc::Rc;
 use std::cell::RefCell;
 
@@ -7,6 +7,10 @@ use std::cell::RefCell;
 pub struct EnvType {
     pub outer: Option<Env>,
     pub data: HashMap<String, MalType>,
+    /// Names declared with `def-dynamic!`, which `binding` may rebind.
+    pub dynamic: HashSet<String>,
+    /// The `def!` or `defmacro!` form that defined each name, for `source`.
+    pub sources: HashMap<String, MalType>,
 }
 
 #[derive(Debug, Clone)]
@@ -17,6 +21,8 @@ impl Env {
         Env(Rc::new(RefCell::new(EnvType {
             outer: outer.map(|e| e.clone()),
             data: HashMap::new(),
+            dynamic: HashSet::new(),
+            sources: HashMap::new(),
         })))
     }
 
@@ -44,6 +50,32 @@ impl Env {
         self.0.borrow_mut().data.insert(key.to_string(), val);
     }
 
+    /// Sets a value and marks the name as a dynamic var.
+    pub fn set_dynamic(&self, key: &str, val: MalType) {
+        self.set(key, val);
+        self.0.borrow_mut().dynamic.insert(key.to_string());
+    }
+
+    /// Records the form that defined a name.
+    pub fn set_source(&self, key: &str, form: MalType) {
+        self.0.borrow_mut().sources.insert(key.to_string(), form);
+    }
+
+    /// The form that defined the nearest binding of the name, if it was
+    /// defined by `def!` or `defmacro!`.
+    pub fn source(&self, key: &str) -> Option<MalType> {
+        self.find(key)
+            .and_then(|env| env.0.borrow().sources.get(key).cloned())
+    }
+
+    /// Whether the nearest binding of the name is a dynamic var.
+    pub fn is_dynamic(&self, key: &str) -> bool {
+        match self.find(key) {
+            Some(env) => env.0.borrow().dynamic.contains(key),
+            None => false,
+        }
+    }
+
     pub fn find(&self, key: &str) -> Option<Env> {
         if self.0.borrow().data.contains_key(key) {
             Some(self.clone())
@@ -57,6 +89,18 @@ impl Env {
         }
     }
 
+    /// Every name bound here or in an outer environment, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = BTreeSet::new();
+        let mut env = Some(self.clone());
+        while let Some(current) = env {
+            let inner = current.0.borrow();
+            names.extend(inner.data.keys().cloned());
+            env = inner.outer.clone();
+        }
+        names.into_iter().collect()
+    }
+
     pub fn get(&self, key: &str) -> Result<MalType, MalError> {
         if let Some(env) = self.find(key) {
             if let Some(val) = env.0.borrow().data.get(key) {
@@ -0,0 +1,388 @@
+//! File handles, and the builtins for files, directories and paths.
+//!
+//! `open-reader` and `open-writer` return a file as a value that `read-line`,
+//! `line-seq` and `close` work on. A writer can also be bound to `*out*` so
+//! that `println` writes to it, and a reader to `*in*`, which is `:stdin` by
+//! default, for `read-line`, `line-seq` and `read-all-stdin`. Every failure is a
+//! `MalError::IO` naming the path.
+
+use types::*;
+use env::Env;
+use printer::pr_str;
+use core::mal_bool;
+use util::{option, string_arg};
+
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// An open file. Closing it drops the handle, after which reading or
+/// writing is an error.
+pub struct MalFile {
+    pub path: String,
+    handle: RefCell<Option<Handle>>,
+}
+
+enum Handle {
+    Reader(BufReader<File>),
+    Writer(BufWriter<File>),
+}
+
+impl MalFile {
+    /// The next line without its line ending, or `None` at the end.
+    pub fn read_line(&self) -> Result<Option<String>, MalError> {
+        match *self.handle.borrow_mut() {
+            Some(Handle::Reader(ref mut reader)) => read_line_from(reader, &self.path),
+            Some(Handle::Writer(_)) => Err(self.error("is not open for reading")),
+            None => Err(self.error("is closed")),
+        }
+    }
+
+    /// Everything left to read.
+    pub fn read_rest(&self) -> Result<String, MalError> {
+        match *self.handle.borrow_mut() {
+            Some(Handle::Reader(ref mut reader)) => read_rest_from(reader, &self.path),
+            Some(Handle::Writer(_)) => Err(self.error("is not open for reading")),
+            None => Err(self.error("is closed")),
+        }
+    }
+
+    /// Calls `f` with the writer.
+    pub fn write<F>(&self, f: F) -> Result<(), MalError>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        match *self.handle.borrow_mut() {
+            Some(Handle::Writer(ref mut writer)) => {
+                f(writer).map_err(|err| io_error(&self.path, err))
+            }
+            Some(Handle::Reader(_)) => Err(self.error("is not open for writing")),
+            None => Err(self.error("is closed")),
+        }
+    }
+
+    /// Flushes a writer and drops the handle. Closing twice does nothing.
+    pub fn close(&self) -> Result<(), MalError> {
+        if let Some(Handle::Writer(mut writer)) = self.handle.borrow_mut().take() {
+            writer.flush().map_err(|err| io_error(&self.path, err))?;
+        }
+        Ok(())
+    }
+
+    fn error(&self, problem: &str) -> MalError {
+        MalError::IO(format!("{}: file {}", self.path, problem))
+    }
+}
+
+fn io_error(path: &str, err: io::Error) -> MalError {
+    MalError::IO(format!("{}: {}", path, err))
+}
+
+fn read_line_from<R: BufRead>(reader: &mut R, path: &str) -> Result<Option<String>, MalError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(|err| io_error(path, err))? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+fn read_rest_from<R: Read>(reader: &mut R, path: &str) -> Result<String, MalError> {
+    let mut rest = String::new();
+    reader
+        .read_to_string(&mut rest)
+        .map_err(|err| io_error(path, err))?;
+    Ok(rest)
+}
+
+/// Calls `from_file` with the reader in `*in*`, or `from_stdin` with stdin.
+fn with_input<T, F, S>(env: &Option<Env>, from_file: F, from_stdin: S) -> Result<T, MalError>
+where
+    F: FnOnce(&MalFile) -> Result<T, MalError>,
+    S: FnOnce(&mut io::StdinLock) -> Result<T, MalError>,
+{
+    let input = env.as_ref()
+        .and_then(|env| env.get("*in*").ok())
+        .unwrap_or_else(|| MalType::keyword("stdin"));
+    if let Some(file) = input.file_val() {
+        from_file(file)
+    } else if input.keyword_val() == Some("stdin") {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        from_stdin(&mut stdin)
+    } else {
+        Err(MalError::WrongArguments(format!(
+            "Expected :stdin or a reader in *in* but got: {:?}",
+            input
+        )))
+    }
+}
+
+/// Reads a line from the file passed as the first argument, or else from
+/// `*in*`.
+fn next_line(args: &[MalType], env: &Option<Env>) -> Result<Option<String>, MalError> {
+    if !args.is_empty() {
+        return args[0].file_val().unwrap().read_line();
+    }
+    with_input(env, MalFile::read_line, |stdin| read_line_from(stdin, "stdin"))
+}
+
+fn open_for_writing(path: &str, append: bool) -> Result<File, MalError> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|err| io_error(path, err))
+}
+
+fn path_string(path: &Path) -> MalType {
+    MalType::string(path.to_string_lossy().into_owned())
+}
+
+/// `(spit path x)` or `(spit path x {:append true})`
+pub fn spit(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = string_arg(args, 0, "spit")?;
+    let mut file = open_for_writing(path, option(args, 2, "append"))?;
+    file.write_all(pr_str(&args[1], false).as_bytes())
+        .map_err(|err| io_error(path, err))?;
+    Ok(MalType::nil())
+}
+
+pub fn open_reader(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = string_arg(args, 0, "open-reader")?;
+    let file = File::open(path).map_err(|err| io_error(path, err))?;
+    Ok(MalType::file(MalFile {
+        path: path.to_string(),
+        handle: RefCell::new(Some(Handle::Reader(BufReader::new(file)))),
+    }))
+}
+
+/// `(open-writer path)` or `(open-writer path {:append true})`
+pub fn open_writer(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = string_arg(args, 0, "open-writer")?;
+    let file = open_for_writing(path, option(args, 1, "append"))?;
+    Ok(MalType::file(MalFile {
+        path: path.to_string(),
+        handle: RefCell::new(Some(Handle::Writer(BufWriter::new(file)))),
+    }))
+}
+
+pub fn read_line(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    Ok(next_line(args, &env)?
+        .map(MalType::string)
+        .unwrap_or_else(MalType::nil))
+}
+
+/// The rest of the lines of a file or `*in*`, as a list.
+pub fn line_seq(args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let mut lines = vec![];
+    while let Some(line) = next_line(args, &env)? {
+        lines.push(MalType::string(line));
+    }
+    Ok(MalType::list(lines))
+}
+
+/// The rest of `*in*` as a string.
+pub fn read_all_stdin(_args: &mut Vec<MalType>, env: Option<Env>) -> MalResult {
+    let rest = with_input(&env, MalFile::read_rest, |stdin| read_rest_from(stdin, "stdin"))?;
+    Ok(MalType::string(rest))
+}
+
+pub fn close(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    args[0].file_val().unwrap().close()?;
+    Ok(MalType::nil())
+}
+
+pub fn is_file(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(mal_bool(args[0].is_file()))
+}
+
+pub fn file_exists(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(mal_bool(Path::new(string_arg(args, 0, "file-exists?")?).exists()))
+}
+
+pub fn is_directory(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(mal_bool(Path::new(string_arg(args, 0, "directory?")?).is_dir()))
+}
+
+/// Deletes a file or an empty directory.
+pub fn delete_file(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = string_arg(args, 0, "delete-file")?;
+    let is_dir = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.file_type().is_dir(),
+        Err(_) => false,
+    };
+    let result = if is_dir {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|err| io_error(path, err))?;
+    Ok(MalType::nil())
+}
+
+/// Creates a directory and any missing parents.
+pub fn mkdir(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = string_arg(args, 0, "mkdir")?;
+    fs::create_dir_all(path).map_err(|err| io_error(path, err))?;
+    Ok(MalType::nil())
+}
+
+fn entries(path: &Path) -> Result<Vec<PathBuf>, MalError> {
+    let error = |err| io_error(&path.to_string_lossy(), err);
+    let mut entries = vec![];
+    for entry in fs::read_dir(path).map_err(error)? {
+        entries.push(entry.map_err(error)?.path());
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// The sorted names of the entries in a directory.
+pub fn list_dir(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let path = string_arg(args, 0, "list-dir")?;
+    Ok(MalType::vector(
+        entries(Path::new(path))?
+            .iter()
+            .filter_map(|entry| entry.file_name())
+            .map(|name| MalType::string(name.to_string_lossy().into_owned()))
+            .collect(),
+    ))
+}
+
+fn walk(path: &Path, paths: &mut Vec<MalType>) -> Result<(), MalError> {
+    for entry in entries(path)? {
+        paths.push(path_string(&entry));
+        let metadata = fs::symlink_metadata(&entry)
+            .map_err(|err| io_error(&entry.to_string_lossy(), err))?;
+        if metadata.file_type().is_dir() {
+            walk(&entry, paths)?;
+        }
+    }
+    Ok(())
+}
+
+/// The paths of everything under a directory, depth first, each directory
+/// before its contents. Symlinks are listed but not followed.
+pub fn walk_dir(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut paths = vec![];
+    walk(Path::new(string_arg(args, 0, "walk-dir")?), &mut paths)?;
+    Ok(MalType::vector(paths))
+}
+
+pub fn path_join(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let mut path = PathBuf::new();
+    for index in 0..args.len() {
+        path.push(string_arg(args, index, "path-join")?);
+    }
+    Ok(path_string(&path))
+}
+
+/// The last part of a path, or nil for a path like `/` or `..`.
+pub fn basename(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(Path::new(string_arg(args, 0, "basename")?)
+        .file_name()
+        .map(|name| MalType::string(name.to_string_lossy().into_owned()))
+        .unwrap_or_else(MalType::nil))
+}
+
+/// The extension of a path without the dot, or nil.
+pub fn extension(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(Path::new(string_arg(args, 0, "extension")?)
+        .extension()
+        .map(|ext| MalType::string(ext.to_string_lossy().into_owned()))
+        .unwrap_or_else(MalType::nil))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    fn call(func: fn(&mut Vec<MalType>, Option<Env>) -> MalResult, args: Vec<MalType>) -> MalResult {
+        func(&mut args.clone(), None)
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = env::temp_dir().join(format!("mal-files-{}", process::id()));
+        let dir = dir.to_string_lossy().into_owned();
+        let path = format!("{}/sub/notes.txt", dir);
+        call(mkdir, vec![MalType::string(format!("{}/sub", dir))]).unwrap();
+        call(spit, vec![MalType::string(path.clone()), MalType::string("one\r\n")]).unwrap();
+        let mut opts = ::std::collections::BTreeMap::new();
+        opts.insert(MalType::keyword("append"), MalType::bool_true());
+        let append = vec![
+            MalType::string(path.clone()),
+            MalType::string("two"),
+            MalType::hashmap(opts),
+        ];
+        call(spit, append).unwrap();
+        let file = call(open_reader, vec![MalType::string(path.clone())]).unwrap();
+        assert_eq!(call(read_line, vec![file.clone()]).unwrap(), MalType::string("one"));
+        assert_eq!(
+            call(line_seq, vec![file.clone()]).unwrap(),
+            MalType::list(vec![MalType::string("two")])
+        );
+        assert!(call(read_line, vec![file.clone()]).unwrap().is_nil());
+        call(close, vec![file.clone()]).unwrap();
+        match call(read_line, vec![file]) {
+            Err(MalError::IO(_)) => {}
+            other => panic!("expected an IO error, got {:?}", other),
+        }
+        assert_eq!(
+            call(walk_dir, vec![MalType::string(dir.clone())]).unwrap(),
+            MalType::vector(vec![
+                MalType::string(format!("{}/sub", dir)),
+                MalType::string(path.clone()),
+            ])
+        );
+        #[cfg(unix)]
+        {
+            let link = format!("{}/sub/up", dir);
+            ::std::os::unix::fs::symlink("..", &link).unwrap();
+            assert_eq!(
+                call(walk_dir, vec![MalType::string(dir.clone())]).unwrap(),
+                MalType::vector(vec![
+                    MalType::string(format!("{}/sub", dir)),
+                    MalType::string(path.clone()),
+                    MalType::string(link.clone()),
+                ])
+            );
+            fs::remove_file(link).unwrap();
+        }
+        call(delete_file, vec![MalType::string(path.clone())]).unwrap();
+        assert!(call(file_exists, vec![MalType::string(path.clone())]).unwrap().is_false());
+        match call(delete_file, vec![MalType::string(path)]) {
+            Err(MalError::IO(_)) => {}
+            other => panic!("expected an IO error, got {:?}", other),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_paths() {
+        let joined = call(path_join, vec![MalType::string("a"), MalType::string("b.tar.gz")]);
+        assert_eq!(joined.unwrap(), MalType::string("a/b.tar.gz"));
+        assert_eq!(
+            call(basename, vec![MalType::string("a/b.tar.gz")]).unwrap(),
+            MalType::string("b.tar.gz")
+        );
+        assert_eq!(
+            call(extension, vec![MalType::string("a/b.tar.gz")]).unwrap(),
+            MalType::string("gz")
+        );
+        assert!(call(extension, vec![MalType::string("a/b")]).unwrap().is_nil());
+        assert!(call(basename, vec![MalType::string("/")]).unwrap().is_nil());
+    }
+}
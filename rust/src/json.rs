@@ -0,0 +1,216 @@
+//! Conversions between mal values and JSON, and the `json-parse` and
+//! `json-stringify` builtins.
+
+use types::*;
+use env::Env;
+use util::{option, string_arg};
+
+use serde_json::{self, Map, Number, Value};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Converts JSON into mal data with string keys. Numbers that do not fit in
+/// an `i64` are a parse error, as in `json-parse`.
+impl TryFrom<Value> for MalType {
+    type Error = MalError;
+
+    fn try_from(value: Value) -> MalResult {
+        json_to_mal(value, false)
+    }
+}
+
+/// Converts JSON into mal data. Object keys become keywords when
+/// `keywordize` is true and strings otherwise.
+pub fn json_to_mal(value: Value, keywordize: bool) -> MalResult {
+    Ok(match value {
+        Value::Null => MalType::nil(),
+        Value::Bool(true) => MalType::bool_true(),
+        Value::Bool(false) => MalType::bool_false(),
+        Value::Number(number) => match number.as_i64() {
+            Some(num) => MalType::number(num),
+            None => {
+                return Err(MalError::Parse(format!(
+                    "JSON number {} is not a 64-bit integer",
+                    number
+                )))
+            }
+        },
+        Value::String(string) => MalType::string(string),
+        Value::Array(items) => {
+            let items: Result<Vec<MalType>, MalError> = items
+                .into_iter()
+                .map(|item| json_to_mal(item, keywordize))
+                .collect();
+            MalType::vector(items?)
+        }
+        Value::Object(object) => {
+            let mut map = BTreeMap::new();
+            for (key, val) in object {
+                let key = if keywordize {
+                    MalType::keyword(key)
+                } else {
+                    MalType::string(key)
+                };
+                map.insert(key, json_to_mal(val, keywordize)?);
+            }
+            MalType::hashmap(map)
+        }
+    })
+}
+
+/// Converts mal data into JSON. Keywords and symbols become strings, and
+/// map keys may be strings, keywords, symbols or numbers.
+pub fn mal_to_json(value: &MalType) -> Result<Value, MalError> {
+    if value.is_nil() {
+        Ok(Value::Null)
+    } else if value.is_true() {
+        Ok(Value::Bool(true))
+    } else if value.is_false() {
+        Ok(Value::Bool(false))
+    } else if let Some(number) = value.number_val() {
+        Ok(Value::Number(Number::from(number)))
+    } else if let Some(string) = value.string_val() {
+        Ok(Value::String(string.to_string()))
+    } else if let Some(keyword) = value.keyword_val() {
+        Ok(Value::String(keyword.to_string()))
+    } else if let Some(symbol) = value.symbol_val() {
+        Ok(Value::String(symbol.to_string()))
+    } else if let Some(c) = value.char_val() {
+        Ok(Value::String(c.to_string()))
+    } else if let Some(items) = value.list_or_vector_val() {
+        let items: Result<Vec<Value>, MalError> = items.iter().map(mal_to_json).collect();
+        Ok(Value::Array(items?))
+    } else if let Some(set) = value.set_val() {
+        let items: Result<Vec<Value>, MalError> = set.iter().map(mal_to_json).collect();
+        Ok(Value::Array(items?))
+    } else if let Some(map) = value.hashmap_val() {
+        let mut object = Map::new();
+        for (key, val) in map {
+            object.insert(json_key(key)?, mal_to_json(val)?);
+        }
+        Ok(Value::Object(object))
+    } else {
+        Err(MalError::WrongArguments(format!(
+            "Cannot convert {:?} to JSON",
+            value
+        )))
+    }
+}
+
+fn json_key(key: &MalType) -> Result<String, MalError> {
+    if let Some(string) = key.string_val() {
+        Ok(string.to_string())
+    } else if let Some(keyword) = key.keyword_val() {
+        Ok(keyword.to_string())
+    } else if let Some(symbol) = key.symbol_val() {
+        Ok(symbol.to_string())
+    } else if let Some(number) = key.number_val() {
+        Ok(number.to_string())
+    } else {
+        Err(MalError::WrongArguments(format!(
+            "Cannot use {:?} as a JSON object key",
+            key
+        )))
+    }
+}
+
+/// Parses JSON text, reporting errors with the byte offset where they occur.
+pub fn parse(json: &str, keywordize: bool) -> MalResult {
+    match serde_json::from_str(json) {
+        Ok(value) => json_to_mal(value, keywordize),
+        Err(err) => {
+            let offset = byte_offset(json, err.line(), err.column());
+            let message = err.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(index) => &message[..index],
+                None => &message,
+            };
+            Err(MalError::Parse(format!(
+                "Invalid JSON at byte {}: {}",
+                offset, message
+            )))
+        }
+    }
+}
+
+/// serde_json reports a 1-based line and byte column.
+fn byte_offset(json: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = json.split('\n')
+        .take(line.saturating_sub(1))
+        .map(|l| l.len() + 1)
+        .sum();
+    (line_start + column.saturating_sub(1)).min(json.len())
+}
+
+pub fn stringify(value: &MalType, pretty: bool) -> Result<String, MalError> {
+    let json = mal_to_json(value)?;
+    let result = if pretty {
+        serde_json::to_string_pretty(&json)
+    } else {
+        serde_json::to_string(&json)
+    };
+    result.map_err(|err| MalError::WrongArguments(err.to_string()))
+}
+
+/// `(json-parse string)` or `(json-parse string {:keywordize true})`
+pub fn json_parse(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    parse(string_arg(args, 0, "json-parse")?, option(args, 1, "keywordize"))
+}
+
+/// `(json-stringify value)` or `(json-stringify value {:pretty true})`
+pub fn json_stringify(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    Ok(MalType::string(stringify(&args[0], option(args, 1, "pretty"))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::read_str;
+
+    #[test]
+    fn test_parse() {
+        let value = parse(r#"{"a": [1, true, null, "x"], "b": {"c": -2}}"#, true).unwrap();
+        assert_eq!(
+            value,
+            read_str(r#"{:a [1 true nil "x"] :b {:c -2}}"#).unwrap()
+        );
+        let value = parse(r#"{"a": 1}"#, false).unwrap();
+        assert_eq!(value, read_str(r#"{"a" 1}"#).unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("[1,\n 2,]", false).unwrap_err(),
+            MalError::Parse("Invalid JSON at byte 7: trailing comma".to_string())
+        );
+        assert!(parse("1.5", false).is_err());
+    }
+
+    #[test]
+    fn test_stringify() {
+        let value = read_str(r#"{:name "mal" :tags [:a "b"] :n nil}"#).unwrap();
+        assert_eq!(
+            stringify(&value, false).unwrap(),
+            r#"{"n":null,"name":"mal","tags":["a","b"]}"#
+        );
+        assert_eq!(stringify(&read_str("[1]").unwrap(), true).unwrap(), "[\n  1\n]");
+        assert!(stringify(&MalType::atom(MalType::nil()), false).is_err());
+    }
+
+    #[test]
+    fn test_try_from_value() {
+        let value: Value = serde_json::from_str(r#"{"a": [1, -2]}"#).unwrap();
+        assert_eq!(
+            MalType::try_from(value).unwrap(),
+            read_str(r#"{"a" [1 -2]}"#).unwrap()
+        );
+        for json in &["[1, 2.5]", "18446744073709551615"] {
+            let value: Value = serde_json::from_str(json).unwrap();
+            assert_eq!(
+                MalType::try_from(value).unwrap_err(),
+                parse(json, false).unwrap_err()
+            );
+        }
+    }
+}
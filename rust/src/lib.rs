@@ -3,12 +3,27 @@ extern crate lazy_static;
 
 extern crate linefeed;
 extern crate regex;
+extern crate rmp;
+extern crate serde;
+#[cfg(test)]
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 extern crate time;
 
+pub mod binary;
 pub mod core;
 pub mod env;
+pub mod files;
+pub mod json;
+pub mod output;
+pub mod pprint;
 pub mod printer;
 pub mod reader;
 pub mod readline;
+pub mod system;
 pub mod types;
 pub mod util;
+pub mod value;
+
+pub use value::{from_value, to_value};
@@ -0,0 +1,141 @@
+//! Where printed output goes.
+//!
+//! The printing builtins write to the target in `*out*`, which is `:stdout`,
+//! `:stderr`, a file from `open-writer`, or an atom holding a string that
+//! output is appended to, as `with-out-str` uses. `:stdout` and `:stderr` are
+//! sinks that default to the process streams, and Rust code embedding the
+//! interpreter can replace them to collect output:
+//!
+//! ```
+//! use mal_rust::output::{self, Buffer};
+//!
+//! let buffer = Buffer::new();
+//! output::set_stdout(Box::new(buffer.clone()));
+//! // ... evaluate some mal ...
+//! let printed = buffer.contents();
+//! ```
+
+use types::*;
+use env::Env;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+thread_local! {
+    static STDOUT: RefCell<Box<dyn Write>> = RefCell::new(Box::new(io::stdout()));
+    static STDERR: RefCell<Box<dyn Write>> = RefCell::new(Box::new(io::stderr()));
+}
+
+/// Replaces the sink behind `:stdout` on this thread, returning the old one.
+pub fn set_stdout(sink: Box<dyn Write>) -> Box<dyn Write> {
+    STDOUT.with(|cell| cell.replace(sink))
+}
+
+/// Replaces the sink behind `:stderr` on this thread, returning the old one.
+pub fn set_stderr(sink: Box<dyn Write>) -> Box<dyn Write> {
+    STDERR.with(|cell| cell.replace(sink))
+}
+
+/// A shared in-memory sink. Clones write to the same bytes.
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer::default()
+    }
+
+    /// Everything written so far, with invalid UTF-8 replaced.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The target in `*out*`, or `:stdout` without an environment.
+pub fn out(env: &Option<Env>) -> MalType {
+    target(env, "*out*", "stdout")
+}
+
+/// The target in `*err*`, or `:stderr` without an environment.
+pub fn err(env: &Option<Env>) -> MalType {
+    target(env, "*err*", "stderr")
+}
+
+fn target(env: &Option<Env>, name: &str, default: &str) -> MalType {
+    env.as_ref()
+        .and_then(|env| env.get(name).ok())
+        .unwrap_or_else(|| MalType::keyword(default))
+}
+
+/// Calls `f` with a writer for the target and flushes it.
+pub fn write_to<F>(target: &MalType, f: F) -> Result<(), MalError>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    if let Some(atom) = target.atom_val() {
+        let mut bytes = vec![];
+        f(&mut bytes)?;
+        let current = atom.borrow().clone();
+        let mut text = match current.string_val() {
+            Some(text) => text.to_string(),
+            None if current.is_nil() => String::new(),
+            None => {
+                return Err(MalError::WrongArguments(format!(
+                    "An output atom must hold a string but got: {:?}",
+                    current
+                )))
+            }
+        };
+        text.push_str(&String::from_utf8_lossy(&bytes));
+        atom.replace(MalType::string(text));
+        return Ok(());
+    }
+    if let Some(file) = target.file_val() {
+        return file.write(f);
+    }
+    let sink = match target.keyword_val() {
+        Some("stdout") => &STDOUT,
+        Some("stderr") => &STDERR,
+        _ => {
+            return Err(MalError::WrongArguments(format!(
+                "Expected :stdout, :stderr, a writer or an atom as an output target but got: {:?}",
+                target
+            )))
+        }
+    };
+    sink.with(|cell| {
+        let mut sink = cell.borrow_mut();
+        f(&mut **sink)?;
+        sink.flush()
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_to() {
+        let buffer = Buffer::new();
+        set_stderr(Box::new(buffer.clone()));
+        write_to(&MalType::keyword("stderr"), |out| out.write_all(b"oops\n")).unwrap();
+        assert_eq!(buffer.contents(), "oops\n");
+        let atom = MalType::atom(MalType::string("a"));
+        write_to(&atom, |out| out.write_all(b"b")).unwrap();
+        assert_eq!(atom.atom_val().unwrap().borrow().string_val(), Some("ab"));
+        assert!(write_to(&MalType::number(1), |out| out.write_all(b"x")).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Width-aware pretty printing, after Wadler's "A prettier printer".
+//!
+//! A value is first turned into a `Doc` describing where it may break, then
+//! laid out so each group stays on one line when it fits in `width` columns.
+
+use types::*;
+use printer::{pr_str_with, PrintOptions};
+
+use std::collections::HashMap;
+
+enum Doc {
+    Text(String),
+    /// A space when its group is flat, otherwise a newline plus indentation.
+    Line,
+    /// Indents lines inside by this many columns more than the enclosing indent.
+    Nest(usize, Box<Doc>),
+    /// Indents lines inside to the column where this doc starts.
+    Align(Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+pub struct PrettyOptions {
+    pub width: usize,
+    /// Whether strings are quoted, and the `length` and `level` limits, which
+    /// work as in `pr_str_with`.
+    pub print: PrintOptions,
+    /// For a list whose head is one of these symbols, how many arguments stay
+    /// on the first line before the body is indented by two columns.
+    pub indent_rules: HashMap<String, usize>,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> PrettyOptions {
+        let mut indent_rules = HashMap::new();
+        for &(name, count) in &[
+            ("def!", 1),
+            ("defmacro!", 1),
+            ("def-dynamic!", 1),
+            ("fn*", 1),
+            ("let*", 1),
+            ("if", 1),
+            ("do", 0),
+            ("try*", 0),
+            ("catch*", 1),
+            ("finally*", 0),
+            ("when", 1),
+            ("when-let", 1),
+            ("if-let", 1),
+            ("binding", 1),
+            ("doseq", 1),
+            ("dotimes", 1),
+        ] {
+            indent_rules.insert(name.to_string(), count);
+        }
+        PrettyOptions {
+            width: 80,
+            print: PrintOptions::readably(true),
+            indent_rules,
+        }
+    }
+}
+
+impl PrettyOptions {
+    pub fn width(mut self, width: usize) -> PrettyOptions {
+        self.width = width;
+        self
+    }
+
+    pub fn print(mut self, print: PrintOptions) -> PrettyOptions {
+        self.print = print;
+        self
+    }
+
+    pub fn indent_rule<S: Into<String>>(mut self, name: S, count: usize) -> PrettyOptions {
+        self.indent_rules.insert(name.into(), count);
+        self
+    }
+}
+
+pub fn pretty_str(value: &MalType, opts: &PrettyOptions) -> String {
+    let doc = to_doc(value, opts, 0);
+    layout(&doc, opts.width)
+}
+
+/// Builds the doc for a value nested `depth` collections deep. Collections
+/// past `*print-level*` become `#`, and items past `*print-length*` are left
+/// out as `...`, like the plain printer.
+fn to_doc(value: &MalType, opts: &PrettyOptions, depth: usize) -> Doc {
+    let print = &opts.print;
+    let is_collection =
+        value.is_list() || value.is_vector() || value.is_hashmap() || value.is_set();
+    if is_collection && print.too_deep(depth) {
+        return Doc::Text("#".to_string());
+    }
+    if let Some(list) = value.list_val() {
+        list_doc(list, opts, depth + 1)
+    } else if let Some(vector) = value.vector_val() {
+        let items = vector.iter().map(|item| to_doc(item, opts, depth + 1));
+        seq_doc("[", limited(print, items), "]")
+    } else if let Some(map) = value.hashmap_val() {
+        let pairs = map.iter().map(|(key, val)| {
+            Doc::Concat(vec![
+                to_doc(key, opts, depth + 1),
+                Doc::Text(" ".to_string()),
+                to_doc(val, opts, depth + 1),
+            ])
+        });
+        seq_doc("{", limited(print, pairs), "}")
+    } else if let Some(set) = value.set_val() {
+        let items = set.iter().map(|item| to_doc(item, opts, depth + 1));
+        seq_doc("#{", limited(print, items), "}")
+    } else {
+        // Atoms, fns and ex-info can hold collections, which get whatever
+        // levels are left.
+        let leaf = PrintOptions {
+            print_readably: print.print_readably,
+            length: print.length,
+            level: print.level.map(|level| level.saturating_sub(depth)),
+        };
+        Doc::Text(pr_str_with(value, &leaf))
+    }
+}
+
+/// Takes items up to `*print-length*`, with `...` after them when some are
+/// left out. Later items are never built.
+fn limited<I: Iterator<Item = Doc>>(print: &PrintOptions, items: I) -> Vec<Doc> {
+    let mut docs = vec![];
+    for (index, item) in items.enumerate() {
+        if print.past_length(index) {
+            docs.push(Doc::Text("...".to_string()));
+            break;
+        }
+        docs.push(item);
+    }
+    docs
+}
+
+/// Lays out a list whose items are `depth` collections deep.
+fn list_doc(list: &[MalType], opts: &PrettyOptions, depth: usize) -> Doc {
+    let head = match list.first().and_then(|item| item.symbol_val()) {
+        Some(head) if !opts.print.past_length(0) => head,
+        _ => {
+            let items = list.iter().map(|item| to_doc(item, opts, depth));
+            return seq_doc("(", limited(&opts.print, items), ")");
+        }
+    };
+    let mut rest = limited(
+        &opts.print,
+        list.iter().map(|item| to_doc(item, opts, depth)),
+    );
+    rest.remove(0);
+    let mut parts = vec![Doc::Text("(".to_string() + head)];
+    if let Some(&count) = opts.indent_rules.get(head) {
+        // (let* [bindings]
+        //   body)
+        let count = count.min(rest.len());
+        let body: Vec<Doc> = rest
+            .split_off(count)
+            .into_iter()
+            .flat_map(|item| vec![Doc::Line, item])
+            .collect();
+        for item in rest {
+            parts.push(Doc::Text(" ".to_string()));
+            parts.push(item);
+        }
+        parts.push(Doc::Nest(2, Box::new(Doc::Concat(body))));
+    } else if !rest.is_empty() {
+        // (call first-arg
+        //       second-arg)
+        parts.push(Doc::Text(" ".to_string()));
+        parts.push(Doc::Align(Box::new(separated(rest))));
+    }
+    parts.push(Doc::Text(")".to_string()));
+    Doc::Group(Box::new(Doc::Align(Box::new(Doc::Concat(parts)))))
+}
+
+fn seq_doc(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    Doc::Group(Box::new(Doc::Concat(vec![
+        Doc::Text(open.to_string()),
+        Doc::Align(Box::new(separated(items))),
+        Doc::Text(close.to_string()),
+    ])))
+}
+
+fn separated(items: Vec<Doc>) -> Doc {
+    let mut parts = vec![];
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            parts.push(Doc::Line);
+        }
+        parts.push(item);
+    }
+    Doc::Concat(parts)
+}
+
+fn layout(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack: Vec<(usize, Mode, &Doc)> = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match *doc {
+            Doc::Text(ref text) => {
+                out.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Line => {
+                if mode == Mode::Flat {
+                    out.push(' ');
+                    column += 1;
+                } else {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+            }
+            Doc::Nest(extra, ref inner) => stack.push((indent + extra, mode, inner)),
+            Doc::Align(ref inner) => stack.push((column, mode, inner)),
+            Doc::Group(ref inner) => {
+                let remaining = width as isize - column as isize;
+                if mode == Mode::Flat || fits(remaining, inner, &stack) {
+                    stack.push((indent, Mode::Flat, inner));
+                } else {
+                    stack.push((indent, Mode::Break, inner));
+                }
+            }
+            Doc::Concat(ref parts) => {
+                for part in parts.iter().rev() {
+                    stack.push((indent, mode, part));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Whether `doc` laid out flat, followed by whatever comes after it up to
+/// the next line break, fits in `remaining` columns.
+fn fits(mut remaining: isize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut pending: Vec<(Mode, &Doc)> = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+    while remaining >= 0 {
+        let (mode, doc) = match pending.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match *doc {
+            Doc::Text(ref text) => remaining -= text.chars().count() as isize,
+            Doc::Line => {
+                if mode == Mode::Break {
+                    return true;
+                }
+                remaining -= 1;
+            }
+            Doc::Nest(_, ref inner) | Doc::Align(ref inner) | Doc::Group(ref inner) => {
+                pending.push((mode, inner))
+            }
+            Doc::Concat(ref parts) => {
+                for part in parts.iter().rev() {
+                    pending.push((mode, part));
+                }
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::read_str;
+
+    fn pretty(code: &str, width: usize) -> String {
+        let ast = read_str(code).unwrap();
+        pretty_str(&ast, &PrettyOptions::default().width(width))
+    }
+
+    #[test]
+    fn test_fits_on_one_line() {
+        assert_eq!(pretty("(+ 1 [2 3] {:a 4})", 80), "(+ 1 [2 3] {:a 4})");
+    }
+
+    #[test]
+    fn test_breaks_to_width() {
+        assert_eq!(
+            pretty("{:abc 123 :def {:ghi 456 :jkl [789 \"ten eleven twelve\"]}}", 30),
+            "{:abc 123\n :def {:ghi 456\n       :jkl [789\n             \"ten eleven twelve\"]}}"
+        );
+        assert_eq!(
+            pretty("(foo 1 (bar 2 3) 4)", 15),
+            "(foo 1\n     (bar 2 3)\n     4)"
+        );
+    }
+
+    #[test]
+    fn test_indent_rules() {
+        assert_eq!(
+            pretty("(def! f (fn* [a b] (let* [c (+ a b)] (* c c))))", 30),
+            "(def! f\n  (fn* [a b]\n    (let* [c (+ a b)]\n      (* c c))))"
+        );
+        let ast = read_str("(my-when ok (do-a) (do-b))").unwrap();
+        let opts = PrettyOptions::default().width(15).indent_rule("my-when", 1);
+        assert_eq!(pretty_str(&ast, &opts), "(my-when ok\n  (do-a)\n  (do-b))");
+    }
+
+    #[test]
+    fn test_print_limits() {
+        let ast = read_str("[1 [2 [3 [4]]] {:a 1 :b 2 :c 3} 5 6]").unwrap();
+        let limits = |length, level| PrintOptions {
+            print_readably: true,
+            length,
+            level,
+        };
+        let opts = PrettyOptions::default().print(limits(Some(3), Some(2)));
+        assert_eq!(pretty_str(&ast, &opts), "[1 [2 #] {:a 1 :b 2 :c 3} ...]");
+        let opts = PrettyOptions::default().width(12).print(limits(Some(2), None));
+        assert_eq!(pretty_str(&ast, &opts), "[1\n [2 [3 [4]]]\n ...]");
+        let ast = read_str("(let* [a 1 b 2] (+ a b) (* a b))").unwrap();
+        let opts = PrettyOptions::default().width(12).print(limits(Some(3), Some(1)));
+        assert_eq!(pretty_str(&ast, &opts), "(let* #\n  #\n  ...)");
+        let opts = PrettyOptions::default().print(limits(Some(0), None));
+        assert_eq!(pretty_str(&ast, &opts), "(...)");
+        let atom = MalType::atom(read_str("[[1]]").unwrap());
+        let opts = PrettyOptions::default().print(limits(None, Some(2)));
+        assert_eq!(pretty_str(&MalType::vector(vec![atom]), &opts), "[(atom [#])]");
+    }
+}
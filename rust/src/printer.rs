@@ -1,72 +1,268 @@
 use types::*;
+use env::Env;
+use reader::CHAR_NAMES;
 use std::collections::BTreeMap;
-use regex::Regex;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Controls how values are printed. `length` limits how many items of each
+/// collection are shown and `level` how deeply collections are nested, like
+/// Clojure's `*print-length*` and `*print-level*`.
+pub struct PrintOptions {
+    pub print_readably: bool,
+    pub length: Option<usize>,
+    pub level: Option<usize>,
+}
+
+impl PrintOptions {
+    pub fn readably(print_readably: bool) -> PrintOptions {
+        PrintOptions {
+            print_readably,
+            length: None,
+            level: None,
+        }
+    }
+
+    /// Reads the limits from `*print-length*` and `*print-level*`, where nil
+    /// or an undefined var means no limit.
+    pub fn from_env(env: &Env, print_readably: bool) -> PrintOptions {
+        let limit = |name: &str| {
+            env.get(name)
+                .ok()
+                .and_then(|val| val.number_val())
+                .map(|num| num.max(0) as usize)
+        };
+        PrintOptions {
+            print_readably,
+            length: limit("*print-length*"),
+            level: limit("*print-level*"),
+        }
+    }
+
+    /// Whether a collection at `depth` is printed as `#`.
+    pub fn too_deep(&self, depth: usize) -> bool {
+        match self.level {
+            Some(level) => depth >= level,
+            None => false,
+        }
+    }
+
+    /// Whether the item at `index` of a collection is left out as `...`.
+    pub fn past_length(&self, index: usize) -> bool {
+        match self.length {
+            Some(length) => index >= length,
+            None => false,
+        }
+    }
+}
 
 pub fn pr_str(value: &MalType, print_readably: bool) -> String {
-    if value.is_nil() {
-        "nil".to_string()
-    } else if value.is_true() {
-        "true".to_string()
-    } else if value.is_false() {
-        "false".to_string()
-    } else if let Some(number) = value.number_val() {
-        number.to_string()
-    } else if let Some(symbol) = value.symbol_val() {
-        symbol.to_string()
-    } else if let Some(keyword) = value.keyword_val() {
-        ":".to_string() + keyword
-    } else if let Some(string) = value.string_val() {
-        if print_readably {
-            unescape_single_quotes(&format!("{:?}", string))
+    pr_str_with(value, &PrintOptions::readably(print_readably))
+}
+
+pub fn pr_str_with(value: &MalType, opts: &PrintOptions) -> String {
+    let mut out = vec![];
+    write_form(&mut out, value, opts).expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("printer wrote invalid UTF-8")
+}
+
+/// Prints a value straight to `out`, without building the whole string in
+/// memory first.
+pub fn write_form<W: Write>(out: &mut W, value: &MalType, opts: &PrintOptions) -> io::Result<()> {
+    let mut printer = Printer {
+        out,
+        opts,
+        depth: 0,
+        atoms: vec![],
+    };
+    printer.write(value)
+}
+
+struct Printer<'a, W: Write + 'a> {
+    out: &'a mut W,
+    opts: &'a PrintOptions,
+    depth: usize,
+    /// Atoms currently being printed, to spot an atom that contains itself.
+    atoms: Vec<MalType>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    fn write(&mut self, value: &MalType) -> io::Result<()> {
+        let print_readably = self.opts.print_readably;
+        if value.is_nil() {
+            self.out.write_all(b"nil")
+        } else if value.is_true() {
+            self.out.write_all(b"true")
+        } else if value.is_false() {
+            self.out.write_all(b"false")
+        } else if let Some(number) = value.number_val() {
+            write!(self.out, "{}", number)
+        } else if let Some(symbol) = value.symbol_val() {
+            self.out.write_all(symbol.as_bytes())
+        } else if let Some(keyword) = value.keyword_val() {
+            write!(self.out, ":{}", keyword)
+        } else if let Some(string) = value.string_val() {
+            if print_readably {
+                write_string(self.out, string)
+            } else {
+                self.out.write_all(string.as_bytes())
+            }
+        } else if let Some(c) = value.char_val() {
+            if print_readably {
+                write_char(self.out, c)
+            } else {
+                write!(self.out, "{}", c)
+            }
+        } else if let Some(list) = value.list_val() {
+            self.write_list(list, b"(", b")")
+        } else if let Some(vector) = value.vector_val() {
+            self.write_list(vector, b"[", b"]")
+        } else if let Some(map) = value.hashmap_val() {
+            self.write_map(map)
+        } else if let Some(set) = value.set_val() {
+            self.write_list(set, b"#{", b"}")
+        } else if value.is_function() {
+            self.out.write_all(b"#<function>")
+        } else if let Some(lambda) = value.lambda_val() {
+            self.out
+                .write_all(if lambda.is_macro { b"#<macro " } else { b"#<fn " })?;
+            if let Some(ref name) = lambda.name {
+                write!(self.out, "{} ", name)?;
+            }
+            self.write_list(&lambda.args, b"[", b"]")?;
+            self.out.write_all(b">")
+        } else if let Some(regex) = value.regex_val() {
+            write_regex(self.out, regex.as_str())
+        } else if let Some((message, data)) = value.ex_info_val() {
+            self.out.write_all(b"#<ex-info ")?;
+            write_string(self.out, message)?;
+            self.out.write_all(b" ")?;
+            self.write(data)?;
+            self.out.write_all(b">")
+        } else if let Some(file) = value.file_val() {
+            write!(self.out, "#<file {}>", file.path)
+        } else if let Some(atom) = value.atom_val() {
+            if self.atoms.iter().any(|seen| Rc::ptr_eq(&seen.0, &value.0)) {
+                return self.out.write_all(b"#<cycle>");
+            }
+            self.atoms.push(value.clone());
+            self.out.write_all(b"(atom ")?;
+            self.write(&atom.borrow())?;
+            self.atoms.pop();
+            self.out.write_all(b")")
         } else {
-            string.to_owned()
+            panic!("Unknown type")
         }
-    } else if let Some(list) = value.list_val() {
-        pr_list(list, '(', ')', print_readably)
-    } else if let Some(vector) = value.vector_val() {
-        pr_list(vector, '[', ']', print_readably)
-    } else if let Some(map) = value.hashmap_val() {
-        pr_map(map, print_readably)
-    } else if value.is_function() {
-        "#<function>".to_string()
-    } else if value.is_lambda() {
-        "#<function>".to_string()
-    } else if let Some(atom) = value.atom_val() {
-        format!("(atom {})", pr_str(&(*atom.borrow()), print_readably)).to_string()
-    } else {
-        panic!("Unknown type")
+    }
+
+    fn write_list<'b, I>(&mut self, list: I, open: &[u8], close: &[u8]) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'b MalType>,
+    {
+        if self.opts.too_deep(self.depth) {
+            return self.out.write_all(b"#");
+        }
+        self.out.write_all(open)?;
+        self.depth += 1;
+        for (index, item) in list.into_iter().enumerate() {
+            if index > 0 {
+                self.out.write_all(b" ")?;
+            }
+            if self.opts.past_length(index) {
+                self.out.write_all(b"...")?;
+                break;
+            }
+            self.write(item)?;
+        }
+        self.depth -= 1;
+        self.out.write_all(close)
+    }
+
+    fn write_map(&mut self, map: &BTreeMap<MalType, MalType>) -> io::Result<()> {
+        if self.opts.too_deep(self.depth) {
+            return self.out.write_all(b"#");
+        }
+        self.out.write_all(b"{")?;
+        self.depth += 1;
+        for (index, (key, val)) in map.iter().enumerate() {
+            if index > 0 {
+                self.out.write_all(b" ")?;
+            }
+            if self.opts.past_length(index) {
+                self.out.write_all(b"...")?;
+                break;
+            }
+            self.write(key)?;
+            self.out.write_all(b" ")?;
+            self.write(val)?;
+        }
+        self.depth -= 1;
+        self.out.write_all(b"}")
     }
 }
 
-fn pr_list(list: &Vec<MalType>, open: char, close: char, print_readably: bool) -> String {
-    let mut str = String::new();
-    str.push(open);
-    let atoms: Vec<String> = list.iter()
-        .map(|atom| pr_str(atom, print_readably))
-        .collect();
-    str.push_str(&atoms.join(" "));
-    str.push(close);
-    str
+/// Quotes a string so that `read_str` turns it back into the same string.
+/// Control characters without a short escape are written as `\uXXXX`.
+pub fn escape_string(string: &str) -> String {
+    let mut out = vec![];
+    write_string(&mut out, string).expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("printer wrote invalid UTF-8")
 }
 
-fn pr_map(map: &BTreeMap<MalType, MalType>, print_readably: bool) -> String {
-    let mut str = String::new();
-    str.push('{');
-    let pairs: Vec<String> = map.iter()
-        .map(|(key, val)| pr_str(key, print_readably) + " " + &pr_str(val, print_readably))
-        .collect();
-    str.push_str(&pairs.join(" "));
-    str.push('}');
-    str
+fn write_string<W: Write>(out: &mut W, string: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut unescaped = 0;
+    for (index, c) in string.char_indices() {
+        let escape = match c {
+            '"' => Some("\\\""),
+            '\\' => Some("\\\\"),
+            '\n' => Some("\\n"),
+            '\t' => Some("\\t"),
+            '\r' => Some("\\r"),
+            '\0' => Some("\\0"),
+            c if c.is_control() => None,
+            _ => continue,
+        };
+        out.write_all(&string.as_bytes()[unescaped..index])?;
+        match escape {
+            Some(escape) => out.write_all(escape.as_bytes())?,
+            None => write!(out, "\\u{:04X}", c as u32)?,
+        }
+        unescaped = index + c.len_utf8();
+    }
+    out.write_all(&string.as_bytes()[unescaped..])?;
+    out.write_all(b"\"")
 }
 
-const ESCAPED_SINGLE_QUOTE: &str = r#"\\'"#;
+/// Writes a character literal the way `read_str` expects it.
+fn write_char<W: Write>(out: &mut W, c: char) -> io::Result<()> {
+    if let Some(&(name, _)) = CHAR_NAMES.iter().find(|&&(_, named)| named == c) {
+        write!(out, "\\{}", name)
+    } else if c.is_control() {
+        write!(out, "\\u{:04X}", c as u32)
+    } else {
+        write!(out, "\\{}", c)
+    }
+}
 
-/// Rust likes to escape single quotes -- not sure why.
-/// But it breaks our tests.
-fn unescape_single_quotes(string: &str) -> String {
-    let re = Regex::new(ESCAPED_SINGLE_QUOTE).unwrap();
-    re.replace_all(string, "'").into_owned()
+/// Writes a regex literal. The pattern is kept as written, except that a bare
+/// double quote has to be escaped to keep the literal readable.
+fn write_regex<W: Write>(out: &mut W, pattern: &str) -> io::Result<()> {
+    out.write_all(b"#\"")?;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                write!(out, "{}", c)?;
+                if let Some(escaped) = chars.next() {
+                    write!(out, "{}", escaped)?;
+                }
+            }
+            '"' => out.write_all(b"\\\"")?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
 }
 
 #[cfg(test)]
@@ -80,4 +276,64 @@ mod tests {
         let ast = read_str(code).unwrap();
         assert_eq!(pr_str(&ast, false), code);
     }
+
+    #[test]
+    fn test_string_round_trip() {
+        let strings = vec![
+            "plain",
+            "quote \" and backslash \\",
+            "new\nline\ttab\rreturn\0nul",
+            "bell \u{7} delete \u{7f} it's",
+            "unicode é \u{1f600}",
+        ];
+        for string in strings {
+            let printed = pr_str(&MalType::string(string), true);
+            assert_eq!(read_str(&printed).unwrap(), MalType::string(string));
+        }
+        assert_eq!(
+            pr_str(&MalType::string("a\"b\\c\nd\u{1}"), true),
+            r#""a\"b\\c\nd\u0001""#
+        );
+    }
+
+    #[test]
+    fn test_print_limits() {
+        let ast = read_str("[1 [2 [3 [4]]] {:a 1 :b 2 :c 3} 5 6]").unwrap();
+        let opts = PrintOptions {
+            print_readably: true,
+            length: Some(3),
+            level: Some(2),
+        };
+        assert_eq!(pr_str_with(&ast, &opts), "[1 [2 #] {:a 1 :b 2 :c 3} ...]");
+    }
+
+    #[test]
+    fn test_write_form() {
+        let ast = read_str("{:a [1 \"two\" \\3]}").unwrap();
+        let mut out = vec![];
+        write_form(&mut out, &ast, &PrintOptions::readably(true)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{:a [1 \"two\" \\3]}");
+    }
+
+    #[test]
+    fn test_atom_cycle() {
+        let atom = MalType::atom(MalType::nil());
+        atom.atom_val()
+            .unwrap()
+            .replace(MalType::vector(vec![MalType::number(1), atom.clone()]));
+        assert_eq!(pr_str(&atom, true), "(atom [1 #<cycle>])");
+    }
+
+    #[test]
+    fn test_pr_char() {
+        let chars = MalType::list(vec![
+            MalType::char('a'),
+            MalType::char('\n'),
+            MalType::char(' '),
+            MalType::char('\u{1}'),
+            MalType::char('é'),
+        ]);
+        assert_eq!(pr_str(&chars, true), r"(\a \newline \space \u0001 \é)");
+        assert_eq!(pr_str(&MalType::char('é'), false), "é");
+    }
 }
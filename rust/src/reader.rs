@@ -3,7 +3,9 @@ use regex::Regex;
 
 use types::*;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
+use std::str::Chars;
 
 macro_rules! consume_and_assert_eq {
     ( $reader:expr, $expected:expr ) => {
@@ -18,12 +20,14 @@ macro_rules! consume_and_assert_eq {
     };
 }
 
-pub struct Reader {
+pub struct Reader<'a> {
     tokens: Vec<String>,
     position: usize,
+    /// Tag handlers when reading EDN, or `None` for mal code.
+    edn: Option<&'a DataReaders>,
 }
 
-impl Reader {
+impl<'a> Reader<'a> {
     pub fn peek(&self) -> Option<String> {
         if self.tokens.len() > self.position {
             Some(self.tokens[self.position].to_owned())
@@ -47,11 +51,67 @@ pub fn read_str(code: &str) -> MalResult {
     let mut reader = Reader {
         tokens: tokens,
         position: 0,
+        edn: None,
     };
     read_form(&mut reader)
 }
 
-const TOKEN_MATCH: &str = r#"[\s,]*(~@|[\[\]{}()'`~^@]|"(?:\\.|[^\\"])*"|;.*|[^\s\[\]{}('"`,;)]*)"#;
+/// Reads EDN data. Unlike `read_str`, the quoting and metadata reader macros
+/// are rejected and `#tag value` is passed to the matching handler.
+pub fn read_edn(code: &str, readers: &DataReaders) -> MalResult {
+    let tokens = tokenizer(code);
+    let mut reader = Reader {
+        tokens,
+        position: 0,
+        edn: Some(readers),
+    };
+    read_form(&mut reader)
+}
+
+pub type TagHandler = Rc<dyn Fn(MalType) -> MalResult>;
+
+/// The functions that turn the value after an EDN tag into a mal value.
+/// `Default` includes `#inst`, which reads an RFC 3339 timestamp as
+/// milliseconds since the Unix epoch, and `#uuid`, which reads a UUID as a
+/// lowercase string.
+#[derive(Clone)]
+pub struct DataReaders {
+    handlers: HashMap<String, TagHandler>,
+}
+
+impl Default for DataReaders {
+    fn default() -> DataReaders {
+        let mut readers = DataReaders::new();
+        readers.register("inst", |value| match value.string_val() {
+            Some(timestamp) => Ok(MalType::number(parse_inst(timestamp)?)),
+            None => Err(MalError::Parse(format!("#inst expects a string but got: {:?}", value))),
+        });
+        readers.register("uuid", |value| match value.string_val() {
+            Some(uuid) if UUID_MATCH.is_match(uuid) => Ok(MalType::string(uuid.to_lowercase())),
+            _ => Err(MalError::Parse(format!("Invalid #uuid: {:?}", value))),
+        });
+        readers
+    }
+}
+
+impl DataReaders {
+    /// Creates an empty set of readers, without `#inst` or `#uuid`.
+    pub fn new() -> DataReaders {
+        DataReaders {
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn register<S, F>(&mut self, tag: S, handler: F)
+    where
+        S: Into<String>,
+        F: Fn(MalType) -> MalResult + 'static,
+    {
+        self.handlers.insert(tag.into(), Rc::new(handler));
+    }
+}
+
+const TOKEN_MATCH: &str = r#"[\s,]*(~@|#[_{]|[\[\]{}()'`~^@]|#"(?:\\[\s\S]?|[^\\"])*"?|"(?:\\.|[^\\"])*"|;.*|\\(?:[^\s\[\]{}('"`,;)]+|.)|[^\s\[\]{}('"`,;)]*)"#;
 
 fn tokenizer(code: &str) -> Vec<String> {
     let re = Regex::new(TOKEN_MATCH).unwrap();
@@ -77,7 +137,19 @@ fn read_form(reader: &mut Reader) -> MalResult {
         '[' => read_vector(reader),
         '{' => read_hash_map(reader),
         '"' => read_string(reader),
+        '#' => match chars.next() {
+            Some('{') => read_set(reader),
+            Some('_') if token == "#_" => read_discard(reader),
+            Some('"') if reader.edn.is_none() => read_regex(reader),
+            _ if reader.edn.is_some() => read_tagged(reader),
+            _ => read_atom(reader),
+        },
+        '\\' => read_char(reader),
         ':' => read_keyword(reader),
+        '\'' | '~' | '`' | '@' | '^' if reader.edn.is_some() => Err(MalError::Parse(format!(
+            "{} is not valid in EDN",
+            token
+        ))),
         '\'' => read_quote(reader, "quote"),
         '~' => {
             if let Some('@') = chars.next() {
@@ -103,7 +175,7 @@ fn read_string(reader: &mut Reader) -> MalResult {
     loop {
         match chars.next() {
             Some('"') => break,
-            Some('\\') => str.push(unescape_char(chars.next())?),
+            Some('\\') => str.push(unescape_char(&mut chars)?),
             Some(c) => str.push(c),
             None => return Err(MalError::Parse("Unexpected end of string!".to_string())),
         }
@@ -111,6 +183,67 @@ fn read_string(reader: &mut Reader) -> MalResult {
     Ok(MalType::string(str))
 }
 
+/// Named character literals, e.g. `\\newline`.
+pub const CHAR_NAMES: &[(&str, char)] = &[
+    ("newline", '\n'),
+    ("space", ' '),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("backspace", '\u{8}'),
+    ("formfeed", '\u{c}'),
+    ("nul", '\0'),
+];
+
+fn read_char(reader: &mut Reader) -> MalResult {
+    let token = reader.next().unwrap();
+    let name = &token[1..];
+    let mut chars = name.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        (Some('u'), Some(_)) => {
+            let mut chars = name[1..].chars();
+            let c = unescape_unicode(&mut chars)?;
+            if chars.next().is_some() {
+                return Err(MalError::Parse(format!("Unsupported character: {}", token)));
+            }
+            c
+        }
+        _ => match CHAR_NAMES.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, c)) => c,
+            None => return Err(MalError::Parse(format!("Unsupported character: {}", token))),
+        },
+    };
+    Ok(MalType::char(c))
+}
+
+/// Reads `#"..."`. Backslashes are passed through to the regex unchanged, so
+/// `#"\\d+"` needs no double escaping. Only `\\"` is unescaped.
+fn read_regex(reader: &mut Reader) -> MalResult {
+    let token = reader.next().unwrap();
+    let mut pattern = String::new();
+    let mut chars = token[2..].chars();
+    loop {
+        let c = match chars.next() {
+            Some('"') => break,
+            Some(c) => c,
+            None => return Err(MalError::Parse("Unexpected end of regex!".to_string())),
+        };
+        if c == '\\' {
+            match chars.next() {
+                Some('"') => pattern.push('"'),
+                Some(escaped) => {
+                    pattern.push('\\');
+                    pattern.push(escaped);
+                }
+                None => pattern.push('\\'),
+            }
+        } else {
+            pattern.push(c);
+        }
+    }
+    Ok(MalType::regex(MalRegex::new(&pattern)?))
+}
+
 fn read_keyword(reader: &mut Reader) -> MalResult {
     let token = reader.next().unwrap();
     Ok(MalType::keyword(token[1..].to_string()))
@@ -131,14 +264,52 @@ fn read_with_meta(reader: &mut Reader) -> MalResult {
     Ok(list)
 }
 
-fn unescape_char(char: Option<char>) -> Result<char, MalError> {
-    match char {
+fn unescape_char(chars: &mut Chars) -> Result<char, MalError> {
+    match chars.next() {
         Some('n') => Ok('\n'),
-        Some(c) => Ok(c),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('0') => Ok('\0'),
+        Some('\\') => Ok('\\'),
+        Some('"') => Ok('"'),
+        Some('u') => unescape_unicode(chars),
+        Some(c) => Err(MalError::Parse(format!("Unknown escape sequence: \\{}", c))),
         None => Err(MalError::Parse("Unexpected end of string!".to_string())),
     }
 }
 
+/// Reads the XXXX part of a \uXXXX escape. Characters outside the Basic
+/// Multilingual Plane can be written as a UTF-16 surrogate pair.
+fn unescape_unicode(chars: &mut Chars) -> Result<char, MalError> {
+    let high = read_hex4(chars)?;
+    if (0xD800..=0xDBFF).contains(&high) {
+        if chars.next() != Some('\\') || chars.next() != Some('u') {
+            return Err(MalError::Parse(
+                "Expected a low surrogate after \\u escape".to_string(),
+            ));
+        }
+        let low = read_hex4(chars)?;
+        if !(0xDC00..=0xDFFF).contains(&low) {
+            return Err(MalError::Parse(format!("Invalid low surrogate: \\u{:04X}", low)));
+        }
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        return Ok(::std::char::from_u32(code).unwrap());
+    }
+    ::std::char::from_u32(high)
+        .ok_or_else(|| MalError::Parse(format!("Invalid unicode escape: \\u{:04X}", high)))
+}
+
+fn read_hex4(chars: &mut Chars) -> Result<u32, MalError> {
+    let hex: String = chars.take(4).collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(MalError::Parse(format!(
+            "Expected 4 hex digits after \\u but got: {:?}",
+            hex
+        )));
+    }
+    Ok(u32::from_str_radix(&hex, 16).unwrap())
+}
+
 fn read_list(reader: &mut Reader) -> MalResult {
     consume_and_assert_eq!(reader, "(");
     let list = read_list_inner(reader, ")")?;
@@ -161,6 +332,10 @@ fn read_hash_map(reader: &mut Reader) -> MalResult {
     let mut list_iter = list.into_iter();
     loop {
         if let Some(key) = list_iter.next() {
+            // EDN makes duplicate keys invalid, like duplicate set elements.
+            if reader.edn.is_some() && map.contains_key(&key) {
+                return Err(MalError::Parse(format!("Duplicate map key: {:?}", key)));
+            }
             let val = list_iter.next().unwrap();
             map.insert(key, val);
         } else {
@@ -170,6 +345,128 @@ fn read_hash_map(reader: &mut Reader) -> MalResult {
     Ok(MalType::hashmap(map))
 }
 
+fn read_set(reader: &mut Reader) -> MalResult {
+    consume_and_assert_eq!(reader, "#{");
+    let list = read_list_inner(reader, "}")?;
+    let mut set = BTreeSet::new();
+    for item in list {
+        if set.contains(&item) {
+            return Err(MalError::Parse(format!("Duplicate set element: {:?}", item)));
+        }
+        set.insert(item);
+    }
+    Ok(MalType::set(set))
+}
+
+/// Reads the next form, skipping any comments before it. `after` names the
+/// reader macro that needs it, for the error when there is none.
+fn read_next_form(reader: &mut Reader, after: &str) -> MalResult {
+    loop {
+        match reader.peek() {
+            Some(ref token) if !["", ")", "]", "}"].contains(&token.as_str()) => {}
+            Some(ref token) if !token.is_empty() => {
+                return Err(MalError::Parse(format!(
+                    "Expected a form after {} but got {}",
+                    after, token
+                )))
+            }
+            _ => return Err(MalError::Parse(format!("EOF after {}", after))),
+        }
+        match read_form(reader) {
+            Err(MalError::BlankLine) => {}
+            result => return result,
+        }
+    }
+}
+
+/// `#_` drops the form after it. Whatever follows is read in its place, or
+/// nothing at all if a collection ends there. Nothing following at the end
+/// of the input is an error, since there is no form to return.
+fn read_discard(reader: &mut Reader) -> MalResult {
+    consume_and_assert_eq!(reader, "#_");
+    read_next_form(reader, "#_")?;
+    match reader.peek() {
+        Some(ref token) if [")", "]", "}"].contains(&token.as_str()) => Err(MalError::BlankLine),
+        Some(ref token) if !token.is_empty() => read_form(reader),
+        _ => Err(MalError::Parse("EOF after #_".to_string())),
+    }
+}
+
+fn read_tagged(reader: &mut Reader) -> MalResult {
+    let token = reader.next().unwrap();
+    let tag = &token[1..];
+    if !tag.starts_with(char::is_alphabetic) {
+        return Err(MalError::Parse(format!("Invalid tag: {}", token)));
+    }
+    let value = read_next_form(reader, &token)?;
+    let handler = reader
+        .edn
+        .and_then(|readers| readers.handlers.get(tag))
+        .ok_or_else(|| MalError::Parse(format!("No reader function for tag {}", token)))?;
+    handler(value)
+}
+
+lazy_static! {
+    static ref INST_MATCH: Regex = Regex::new(
+        r"^(\d{4})(?:-(\d{2})(?:-(\d{2})(?:T(\d{2}):(\d{2})(?::(\d{2})(?:\.(\d{1,9}))?)?(?:(Z)|([+-])(\d{2}):(\d{2}))?)?)?)?$"
+    ).unwrap();
+    static ref UUID_MATCH: Regex = Regex::new(
+        r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$"
+    ).unwrap();
+}
+
+/// Parses an RFC 3339 timestamp into milliseconds since the Unix epoch. As in
+/// EDN, trailing parts may be left off, e.g. `"2018-03"`, and default to UTC.
+fn parse_inst(timestamp: &str) -> Result<i64, MalError> {
+    let invalid = || MalError::Parse(format!("Invalid #inst: {:?}", timestamp));
+    let caps = INST_MATCH.captures(timestamp).ok_or_else(invalid)?;
+    let part = |index: usize, default: i64| {
+        caps.get(index)
+            .map_or(default, |m| m.as_str().parse::<i64>().unwrap())
+    };
+    let (year, month, day) = (part(1, 0), part(2, 1), part(3, 1));
+    let (hour, minute, second) = (part(4, 0), part(5, 0), part(6, 0));
+    let days_in_month = match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day) || hour > 23
+        || minute > 59 || second > 60
+    {
+        return Err(invalid());
+    }
+    let millis = caps.get(7).map_or(0, |m| {
+        let digits = format!("{:0<3}", m.as_str());
+        digits[..3].parse::<i64>().unwrap()
+    });
+    let offset = match caps.get(9) {
+        Some(sign) => {
+            let minutes = part(10, 0) * 60 + part(11, 0);
+            if sign.as_str() == "-" {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+        None => 0,
+    };
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second
+        - offset * 60;
+    Ok(seconds * 1_000 + millis)
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
 fn read_list_inner(reader: &mut Reader, close: &str) -> Result<Vec<MalType>, MalError> {
     let mut list: Vec<MalType> = Vec::new();
     loop {
@@ -330,6 +627,116 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_string_escapes() {
+        let code = r#""a\nb\tc\rd\\e\"f\0g\u00e9\uD83D\uDE00""#;
+        let ast = read_str(code).unwrap();
+        assert_eq!(ast, MalType::string("a\nb\tc\rd\\e\"f\0g\u{e9}\u{1f600}"));
+        let err = read_str(r#""\q""#).unwrap_err();
+        assert_eq!(err, MalError::Parse("Unknown escape sequence: \\q".to_string()));
+        assert!(read_str(r#""\u12""#).is_err());
+    }
+
+    #[test]
+    fn test_read_char() {
+        let code = r"(\a \newline \space \é \u00e9 \( \\)";
+        let ast = read_str(code).unwrap();
+        assert_eq!(
+            ast,
+            MalType::list(vec![
+                MalType::char('a'),
+                MalType::char('\n'),
+                MalType::char(' '),
+                MalType::char('é'),
+                MalType::char('é'),
+                MalType::char('('),
+                MalType::char('\\'),
+            ])
+        );
+        let err = read_str(r"\foo").unwrap_err();
+        assert_eq!(err, MalError::Parse(r"Unsupported character: \foo".to_string()));
+    }
+
+    #[test]
+    fn test_read_regex() {
+        let ast = read_str(r#"#"\d+\"x""#).unwrap();
+        let regex = ast.regex_val().unwrap();
+        assert_eq!(regex.as_str(), r#"\d+"x"#);
+        assert!(regex.regex.is_match(r#"a12"x"#));
+        assert!(read_str(r#"#"(""#).is_err());
+        for code in &[r#"#"abc"#, r#"[#"a b]"#, r#"#"a\""#, "#\"a\\"] {
+            let err = read_str(code).unwrap_err();
+            assert_eq!(err, MalError::Parse("Unexpected end of regex!".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_read_set() {
+        let ast = read_str("#{1 :a #_ 2 \"b\"}").unwrap();
+        let set = vec![
+            MalType::number(1),
+            MalType::keyword("a"),
+            MalType::string("b"),
+        ];
+        assert_eq!(ast, MalType::set(set.into_iter().collect()));
+        let err = read_str("#{1 1}").unwrap_err();
+        assert_eq!(err, MalError::Parse("Duplicate set element: 1".to_string()));
+    }
+
+    #[test]
+    fn test_read_edn() {
+        let mut readers = DataReaders::default();
+        readers.register("point", |value| {
+            Ok(MalType::list(vec![MalType::symbol("point"), value]))
+        });
+        let code = r#"{:at #inst "1985-04-12T23:20:50.52Z"
+                       :id #uuid "F81D4FAE-7DEC-11D0-A765-00A0C91E6BF6"
+                       :p #point [1 2] #_ :ignored}"#;
+        let ast = read_edn(code, &readers).unwrap();
+        let mut map = BTreeMap::new();
+        map.insert(MalType::keyword("at"), MalType::number(482_196_050_520));
+        map.insert(
+            MalType::keyword("id"),
+            MalType::string("f81d4fae-7dec-11d0-a765-00a0c91e6bf6"),
+        );
+        map.insert(
+            MalType::keyword("p"),
+            MalType::list(vec![
+                MalType::symbol("point"),
+                MalType::vector(vec![MalType::number(1), MalType::number(2)]),
+            ]),
+        );
+        assert_eq!(ast, MalType::hashmap(map));
+        assert_eq!(
+            read_edn("#inst \"2000-01-01T01:00:00+01:00\"", &readers).unwrap(),
+            MalType::number(946_684_800_000)
+        );
+        assert!(read_edn("#unknown 1", &readers).is_err());
+        assert!(read_edn("'a", &readers).is_err());
+        assert!(read_edn("#inst \"2001-02-29\"", &readers).is_err());
+        assert_eq!(
+            read_edn("#point", &readers).unwrap_err(),
+            MalError::Parse("EOF after #point".to_string())
+        );
+        assert_eq!(
+            read_edn("{:a 1 :a 2}", &readers).unwrap_err(),
+            MalError::Parse("Duplicate map key: :a".to_string())
+        );
+        assert!(read_str("{:a 1 :a 2}").is_ok());
+    }
+
+    #[test]
+    fn test_discard_at_eof() {
+        assert_eq!(read_str("#_ 1 2").unwrap(), MalType::number(2));
+        for code in &["#_", "#_ ; comment", "#_ 1", "#_ #_ 1"] {
+            assert_eq!(
+                read_str(code).unwrap_err(),
+                MalError::Parse("EOF after #_".to_string())
+            );
+        }
+        assert!(read_str("[#_]").is_err());
+    }
+
     #[test]
     fn test_comment() {
         let code = "; comment";
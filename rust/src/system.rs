@@ -0,0 +1,278 @@
+//! Builtins for running commands, environment variables, exiting, and
+//! parsing command line arguments.
+
+use types::*;
+use env::Env;
+use util::{seq_result, string_arg};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+fn option_string<'a>(value: &'a MalType, key: &str) -> Result<&'a str, MalError> {
+    match value.string_val() {
+        Some(string) => Ok(string),
+        None => Err(MalError::WrongArguments(format!(
+            "Must pass a string as the :{} of sh but got: {:?}",
+            key, value
+        ))),
+    }
+}
+
+/// `(sh cmd & args)`, optionally ending with an options map holding `:in`,
+/// a string to pass on stdin, and `:dir`, the directory to run in.
+pub fn sh(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let opts = match args.last() {
+        Some(last) if last.is_hashmap() => last.hashmap_val().cloned(),
+        _ => None,
+    };
+    if opts.is_some() {
+        args.pop();
+    }
+    let opts = opts.unwrap_or_else(BTreeMap::new);
+    let program = string_arg(args, 0, "sh")?;
+    let mut command = Command::new(program);
+    for index in 1..args.len() {
+        command.arg(string_arg(args, index, "sh")?);
+    }
+    if let Some(dir) = opts.get(&MalType::keyword("dir")) {
+        command.current_dir(option_string(dir, "dir")?);
+    }
+    let input = match opts.get(&MalType::keyword("in")) {
+        Some(input) => Some(option_string(input, "in")?.to_string()),
+        None => None,
+    };
+    let error = |err| MalError::IO(format!("{}: {}", program, err));
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(error)?;
+    // Write stdin from another thread so a command that fills its stdout
+    // before reading all of its input can't deadlock us.
+    let writer = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => {
+            Some(thread::spawn(move || stdin.write_all(input.as_bytes())))
+        }
+        _ => None,
+    };
+    let output = child.wait_with_output().map_err(error)?;
+    if let Some(writer) = writer {
+        match writer.join() {
+            // The command is free to exit without reading all of its input.
+            Ok(Err(ref err)) if err.kind() == ErrorKind::BrokenPipe => {}
+            Ok(result) => result.map_err(error)?,
+            Err(_) => panic!("the stdin writer for sh panicked"),
+        }
+    }
+    let mut result = BTreeMap::new();
+    result.insert(
+        MalType::keyword("exit"),
+        match output.status.code() {
+            Some(code) => MalType::number(i64::from(code)),
+            None => MalType::nil(),
+        },
+    );
+    result.insert(
+        MalType::keyword("out"),
+        MalType::string(String::from_utf8_lossy(&output.stdout).into_owned()),
+    );
+    result.insert(
+        MalType::keyword("err"),
+        MalType::string(String::from_utf8_lossy(&output.stderr).into_owned()),
+    );
+    Ok(MalType::hashmap(result))
+}
+
+/// `(getenv name)` returns the variable or nil, and `(getenv)` a map of
+/// them all.
+pub fn getenv(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    if args.is_empty() {
+        return Ok(MalType::hashmap(
+            env::vars_os()
+                .map(|(key, val)| {
+                    (
+                        MalType::string(key.to_string_lossy().into_owned()),
+                        MalType::string(val.to_string_lossy().into_owned()),
+                    )
+                })
+                .collect(),
+        ));
+    }
+    Ok(match env::var_os(string_arg(args, 0, "getenv")?) {
+        Some(val) => MalType::string(val.to_string_lossy().into_owned()),
+        None => MalType::nil(),
+    })
+}
+
+/// `(setenv name value)`, or `(setenv name nil)` to remove it. Commands run
+/// by `sh` see the change.
+pub fn setenv(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let name = string_arg(args, 0, "setenv")?;
+    if name.is_empty() || name.contains('=') || name.contains('\0') {
+        return Err(MalError::WrongArguments(format!(
+            "Invalid environment variable name: {:?}",
+            args[0]
+        )));
+    }
+    if args[1].is_nil() {
+        env::remove_var(name);
+    } else {
+        let value = string_arg(args, 1, "setenv")?;
+        if value.contains('\0') {
+            return Err(MalError::WrongArguments(format!(
+                "Invalid environment variable value: {:?}",
+                args[1]
+            )));
+        }
+        env::set_var(name, value);
+    }
+    Ok(MalType::nil())
+}
+
+/// Unwinds with `MalError::Exit`, which `try*` does not catch, for the host
+/// to exit with the status, from 0 to 255.
+pub fn exit(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let status = match args.first() {
+        Some(status) => match status.number_val() {
+            Some(number) if (0..=255).contains(&number) => number as i32,
+            _ => {
+                return Err(MalError::WrongArguments(format!(
+                    "Must pass a status from 0 to 255 to exit but got: {:?}",
+                    status
+                )))
+            }
+        },
+        None => 0,
+    };
+    Err(MalError::Exit(status))
+}
+
+/// `(parse-args argv)` or `(parse-args argv takes-value)` splits command
+/// line arguments into `{:options {...} :args [...]}`.
+///
+/// `--name=value` sets `:name` to the value, and `--name` sets it to true,
+/// unless `name` is one of the strings in `takes-value`, when it takes the
+/// next argument. `-abc` sets `:a`, `:b` and `:c` to true. Everything else,
+/// and everything after `--`, is a positional argument.
+pub fn parse_args(args: &mut Vec<MalType>, _env: Option<Env>) -> MalResult {
+    let argv = seq_result(&args[0])?;
+    let takes_value = match args.get(1) {
+        Some(names) => seq_result(names)?,
+        None => vec![],
+    };
+    let mut options = BTreeMap::new();
+    let mut positional = vec![];
+    let mut iter = argv.iter();
+    while let Some(arg) = iter.next() {
+        let text = match arg.string_val() {
+            Some(text) => text,
+            None => {
+                return Err(MalError::WrongArguments(format!(
+                    "Must pass strings to parse-args but got: {:?}",
+                    arg
+                )))
+            }
+        };
+        if text == "--" {
+            positional.extend(iter.cloned());
+            break;
+        } else if let Some(option) = text.strip_prefix("--") {
+            let (name, value) = match option.find('=') {
+                Some(index) => (&option[..index], MalType::string(&option[index + 1..])),
+                None if takes_value.contains(&MalType::string(option)) => match iter.next() {
+                    Some(value) => (option, value.clone()),
+                    None => {
+                        return Err(MalError::WrongArguments(format!(
+                            "Expected a value after {}",
+                            text
+                        )))
+                    }
+                },
+                None => (option, MalType::bool_true()),
+            };
+            options.insert(MalType::keyword(name), value);
+        } else if let Some(flags) = text.strip_prefix('-').filter(|flags| !flags.is_empty()) {
+            for flag in flags.chars() {
+                options.insert(MalType::keyword(flag.to_string()), MalType::bool_true());
+            }
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+    let mut result = BTreeMap::new();
+    result.insert(MalType::keyword("options"), MalType::hashmap(options));
+    result.insert(MalType::keyword("args"), MalType::vector(positional));
+    Ok(MalType::hashmap(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printer::pr_str;
+
+    fn strings(items: &[&str]) -> MalType {
+        MalType::list(items.iter().map(|item| MalType::string(*item)).collect())
+    }
+
+    #[test]
+    fn test_sh() {
+        let mut opts = BTreeMap::new();
+        opts.insert(MalType::keyword("in"), MalType::string("hello"));
+        let mut args = vec![
+            MalType::string("sh"),
+            MalType::string("-c"),
+            MalType::string("cat; echo oops >&2; exit 3"),
+            MalType::hashmap(opts),
+        ];
+        assert_eq!(
+            pr_str(&sh(&mut args, None).unwrap(), true),
+            "{:err \"oops\\n\" :exit 3 :out \"hello\"}"
+        );
+        let mut opts = BTreeMap::new();
+        opts.insert(MalType::keyword("in"), MalType::string("x".repeat(1 << 20)));
+        let mut args = vec![
+            MalType::string("sh"),
+            MalType::string("-c"),
+            MalType::string("head -c 1; exit 2"),
+            MalType::hashmap(opts),
+        ];
+        let result = sh(&mut args, None).unwrap();
+        assert_eq!(
+            result.hashmap_val().unwrap().get(&MalType::keyword("exit")),
+            Some(&MalType::number(2))
+        );
+        let mut missing = vec![MalType::string("/no/such/command")];
+        match sh(&mut missing, None) {
+            Err(MalError::IO(_)) => {}
+            other => panic!("expected an IO error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_exit() {
+        assert_eq!(exit(&mut vec![MalType::number(3)], None), Err(MalError::Exit(3)));
+        for status in &[-1, 256, 4_294_967_297] {
+            match exit(&mut vec![MalType::number(*status)], None) {
+                Err(MalError::WrongArguments(_)) => {}
+                other => panic!("expected wrong arguments, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let mut args = vec![
+            strings(&["-vq", "--out", "a.txt", "--mode=fast", "--dry", "in.mal", "--", "-x"]),
+            strings(&["out"]),
+        ];
+        assert_eq!(
+            pr_str(&parse_args(&mut args, None).unwrap(), true),
+            "{:args [\"in.mal\" \"-x\"] \
+             :options {:dry true :mode \"fast\" :out \"a.txt\" :q true :v true}}"
+        );
+    }
+}
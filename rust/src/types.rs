@@ -2,13 +2,17 @@ use std::error::Error;
 use std::fmt;
 use std::cmp;
 use std::io;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ptr;
 use std::rc::Rc;
 use std::cell::RefCell;
 
+use regex::Regex;
+
 use printer;
 use env::Env;
 use core::eval_func;
+use files::MalFile;
 
 #[derive(Clone)]
 pub enum _MalType {
@@ -18,13 +22,18 @@ pub enum _MalType {
     Number(i64),
     Keyword(String),
     String(String),
+    Char(char),
     Symbol(String),
     List(Vec<MalType>, MalType),
     Vector(Vec<MalType>, MalType),
     HashMap(BTreeMap<MalType, MalType>, MalType),
+    Set(BTreeSet<MalType>, MalType),
     Function(Function, MalType),
     Lambda(Lambda, MalType),
     Atom(RefCell<MalType>),
+    Regex(MalRegex),
+    File(Rc<MalFile>),
+    ExInfo(String, MalType),
 }
 
 #[derive(Clone)]
@@ -33,6 +42,8 @@ pub struct Lambda {
     pub args: Vec<MalType>,
     pub body: Vec<MalType>,
     pub is_macro: bool,
+    /// The name it was first given by `def!` or `defmacro!`, for printing.
+    pub name: Option<String>,
 }
 
 #[derive(Clone)]
@@ -41,6 +52,30 @@ pub struct Function {
     pub env: Option<Env>,
 }
 
+/// A regular expression compiled once when it is read. `anchored` is the same
+/// pattern wrapped in `^(?:...)$`, used by `re-matches`.
+#[derive(Clone)]
+pub struct MalRegex {
+    pub regex: Regex,
+    pub anchored: Regex,
+}
+
+impl MalRegex {
+    pub fn new(pattern: &str) -> Result<MalRegex, MalError> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|err| MalError::Parse(format!("Invalid regex: {}", err)))
+        };
+        Ok(MalRegex {
+            regex: compile(pattern)?,
+            anchored: compile(&format!("^(?:{})$", pattern))?,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+}
+
 #[derive(Clone)]
 pub struct MalType(pub Rc<_MalType>);
 
@@ -139,6 +174,24 @@ impl MalType {
         }
     }
 
+    pub fn char(val: char) -> MalType {
+        MalType(Rc::new(_MalType::Char(val)))
+    }
+
+    pub fn char_val(&self) -> Option<char> {
+        match *self.0 {
+            _MalType::Char(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn is_char(&self) -> bool {
+        match *self.0 {
+            _MalType::Char(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn symbol<S: Into<String>>(val: S) -> MalType {
         MalType(Rc::new(_MalType::Symbol(val.into())))
     }
@@ -237,6 +290,28 @@ impl MalType {
         }
     }
 
+    pub fn set(set: BTreeSet<MalType>) -> MalType {
+        MalType::set_with_meta(set, MalType::nil())
+    }
+
+    pub fn set_with_meta(set: BTreeSet<MalType>, meta: MalType) -> MalType {
+        MalType(Rc::new(_MalType::Set(set, meta)))
+    }
+
+    pub fn set_val(&self) -> Option<&BTreeSet<MalType>> {
+        match *self.0 {
+            _MalType::Set(ref val, _) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        match *self.0 {
+            _MalType::Set(_, _) => true,
+            _ => false,
+        }
+    }
+
     pub fn function(function: Function) -> MalType {
         MalType::function_with_meta(function, MalType::nil())
     }
@@ -275,22 +350,11 @@ impl MalType {
     }
 
     pub fn make_macro(&mut self) -> MalType {
-        if let _MalType::Lambda(
-            Lambda {
-                ref env,
-                ref args,
-                ref body,
-                ..
-            },
-            ref meta,
-        ) = *self.0
-        {
+        if let _MalType::Lambda(ref lambda, ref meta) = *self.0 {
             MalType::lambda_with_meta(
                 Lambda {
-                    env: env.clone(),
-                    args: args.clone(),
-                    body: body.clone(),
                     is_macro: true,
+                    ..lambda.clone()
                 },
                 meta.clone(),
             )
@@ -299,6 +363,22 @@ impl MalType {
         }
     }
 
+    /// Names a lambda that has no name yet. Anything else is returned as is.
+    pub fn named(&self, name: &str) -> MalType {
+        match *self.0 {
+            _MalType::Lambda(ref lambda, ref meta) if lambda.name.is_none() => {
+                MalType::lambda_with_meta(
+                    Lambda {
+                        name: Some(name.to_string()),
+                        ..lambda.clone()
+                    },
+                    meta.clone(),
+                )
+            }
+            _ => self.clone(),
+        }
+    }
+
     pub fn is_lambda(&self) -> bool {
         match *self.0 {
             _MalType::Lambda { .. } => true,
@@ -324,11 +404,61 @@ impl MalType {
         }
     }
 
+    pub fn regex(regex: MalRegex) -> MalType {
+        MalType(Rc::new(_MalType::Regex(regex)))
+    }
+
+    pub fn regex_val(&self) -> Option<&MalRegex> {
+        match *self.0 {
+            _MalType::Regex(ref regex) => Some(regex),
+            _ => None,
+        }
+    }
+
+    pub fn is_regex(&self) -> bool {
+        match *self.0 {
+            _MalType::Regex(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn file(file: MalFile) -> MalType {
+        MalType(Rc::new(_MalType::File(Rc::new(file))))
+    }
+
+    pub fn file_val(&self) -> Option<&MalFile> {
+        match *self.0 {
+            _MalType::File(ref file) => Some(file),
+            _ => None,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        match *self.0 {
+            _MalType::File(_) => true,
+            _ => false,
+        }
+    }
+
+    /// An error value made by `ex-info`, holding a message and a data map.
+    pub fn ex_info<S: Into<String>>(message: S, data: MalType) -> MalType {
+        MalType(Rc::new(_MalType::ExInfo(message.into(), data)))
+    }
+
+    /// The message and data of an `ex-info` value.
+    pub fn ex_info_val(&self) -> Option<(&str, &MalType)> {
+        match *self.0 {
+            _MalType::ExInfo(ref message, ref data) => Some((message, data)),
+            _ => None,
+        }
+    }
+
     pub fn get_metadata(&self) -> Option<&MalType> {
         match *self.0 {
             _MalType::List(_, ref meta)
             | _MalType::Vector(_, ref meta)
             | _MalType::HashMap(_, ref meta)
+            | _MalType::Set(_, ref meta)
             | _MalType::Function(_, ref meta)
             | _MalType::Lambda(_, ref meta) => Some(meta),
             _ => None,
@@ -340,6 +470,7 @@ impl MalType {
             _MalType::List(ref vec, _) => MalType::list_with_meta(vec.clone(), meta),
             _MalType::Vector(ref vec, _) => MalType::vector_with_meta(vec.clone(), meta),
             _MalType::HashMap(ref map, _) => MalType::hashmap_with_meta(map.clone(), meta),
+            _MalType::Set(ref set, _) => MalType::set_with_meta(set.clone(), meta),
             _MalType::Function(
                 Function {
                     ref func, ref env, ..
@@ -352,23 +483,7 @@ impl MalType {
                 },
                 meta,
             ),
-            _MalType::Lambda(
-                Lambda {
-                    ref env,
-                    ref args,
-                    ref body,
-                    ref is_macro,
-                },
-                _,
-            ) => MalType::lambda_with_meta(
-                Lambda {
-                    env: env.clone(),
-                    args: args.clone(),
-                    body: body.clone(),
-                    is_macro: *is_macro,
-                },
-                meta,
-            ),
+            _MalType::Lambda(ref lambda, _) => MalType::lambda_with_meta(lambda.clone(), meta),
             _ => panic!("not a type with metadata"),
         }
     }
@@ -401,6 +516,8 @@ impl cmp::PartialEq for MalType {
             a == b
         } else if let (Some(a), Some(b)) = (self.string_val(), other.string_val()) {
             a == b
+        } else if let (Some(a), Some(b)) = (self.char_val(), other.char_val()) {
+            a == b
         } else if let (Some(a), Some(b)) = (self.symbol_val(), other.symbol_val()) {
             a == b
         } else if let (Some(a), Some(b)) = (self.list_val(), other.list_val()) {
@@ -409,6 +526,14 @@ impl cmp::PartialEq for MalType {
             a == b
         } else if let (Some(a), Some(b)) = (self.hashmap_val(), other.hashmap_val()) {
             a == b
+        } else if let (Some(a), Some(b)) = (self.set_val(), other.set_val()) {
+            a == b
+        } else if let (Some(a), Some(b)) = (self.regex_val(), other.regex_val()) {
+            a.as_str() == b.as_str()
+        } else if let (Some(a), Some(b)) = (self.ex_info_val(), other.ex_info_val()) {
+            a == b
+        } else if let (Some(a), Some(b)) = (self.file_val(), other.file_val()) {
+            ptr::eq(a, b)
         } else {
             false
         }
@@ -449,12 +574,66 @@ pub enum MalError {
     DivideByZero,
     BlankLine,
     IndexOutOfBounds { size: usize, index: usize },
+    /// Raised by `exit`. Not caught by `try*`; the host exits with it.
+    Exit(i32),
+}
+
+impl MalError {
+    /// The keyword a typed `catch*` clause matches, such as
+    /// `:divide-by-zero`. A thrown `ex-info` uses the `:type` in its data,
+    /// and other thrown values are `:error`.
+    pub fn type_keyword(&self) -> MalType {
+        let name = match *self {
+            MalError::Generic(ref val) => {
+                return val.ex_info_val()
+                    .and_then(|(_, data)| data.hashmap_val().unwrap().get(&MalType::keyword("type")))
+                    .cloned()
+                    .unwrap_or_else(|| MalType::keyword("error"))
+            }
+            MalError::Parse(_) => "parse-error",
+            MalError::SymbolUndefined(_) => "symbol-undefined",
+            MalError::WrongArguments(_) => "wrong-arguments",
+            MalError::NotAFunction(_) => "not-a-function",
+            MalError::IO(_) => "io-error",
+            MalError::NotANumber => "not-a-number",
+            MalError::DivideByZero => "divide-by-zero",
+            MalError::BlankLine => "blank-line",
+            MalError::IndexOutOfBounds { .. } => "index-out-of-bounds",
+            MalError::Exit(_) => "exit",
+        };
+        MalType::keyword(name)
+    }
+
+    /// The value a typed `catch*` clause binds: whatever was thrown, or for
+    /// a built-in error an `ex-info` whose data has its `:type` and details.
+    pub fn to_value(&self) -> MalType {
+        let mut data = BTreeMap::new();
+        match *self {
+            MalError::Generic(ref val) => return val.clone(),
+            MalError::SymbolUndefined(ref sym) => {
+                data.insert(MalType::keyword("symbol"), MalType::symbol(sym.as_str()));
+            }
+            MalError::NotAFunction(ref val) => {
+                data.insert(MalType::keyword("value"), val.clone());
+            }
+            MalError::IndexOutOfBounds { index, size } => {
+                data.insert(MalType::keyword("index"), MalType::number(index as i64));
+                data.insert(MalType::keyword("size"), MalType::number(size as i64));
+            }
+            _ => {}
+        }
+        data.insert(MalType::keyword("type"), self.type_keyword());
+        MalType::ex_info(self.to_string(), MalType::hashmap(data))
+    }
 }
 
 impl fmt::Display for MalError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            MalError::Generic(ref val) => write!(f, "{:?}", val),
+            MalError::Generic(ref val) => match val.ex_info_val() {
+                Some((message, data)) => write!(f, "{} {:?}", message, data),
+                None => write!(f, "{:?}", val),
+            },
             MalError::Parse(ref msg) => write!(f, "Parse error: {}", msg),
             MalError::SymbolUndefined(ref sym) => write!(f, "'{}' not found", sym),
             MalError::WrongArguments(ref msg) => write!(f, "Wrong arguments: {}", msg),
@@ -467,6 +646,7 @@ impl fmt::Display for MalError {
                 ref index,
                 ref size,
             } => write!(f, "Index ({:?}) out of bounds ({:?})", index, size),
+            MalError::Exit(status) => write!(f, "Exit with status {}", status),
         }
     }
 }
@@ -484,6 +664,7 @@ impl Error for MalError {
             MalError::DivideByZero => "Divide by zero",
             MalError::BlankLine => "Blank line",
             MalError::IndexOutOfBounds { .. } => "Index out of bounds",
+            MalError::Exit(_) => "Exit",
         }
     }
 
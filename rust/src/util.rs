@@ -1,4 +1,5 @@
 use types::*;
+use printer::pr_str;
 
 pub fn num_result(arg: &MalType) -> Result<i64, MalError> {
     if let Some(num) = arg.number_val() {
@@ -13,9 +14,52 @@ pub fn num_result(arg: &MalType) -> Result<i64, MalError> {
 pub fn vec_result(arg: &MalType) -> Result<Vec<MalType>, MalError> {
     if let Some(vec) = arg.list_or_vector_val() {
         Ok(vec.clone())
+    } else if let Some(set) = arg.set_val() {
+        Ok(set.iter().cloned().collect())
     } else {
         Err(MalError::WrongArguments(
-            format!("Expected a list or vector but got: {:?}", arg).to_string(),
+            format!("Expected a list, vector or set but got: {:?}", arg).to_string(),
         ))
     }
 }
+
+/// Like `vec_result`, but also accepts nil as empty and a map as its
+/// `[key value]` entries.
+pub fn seq_result(arg: &MalType) -> Result<Vec<MalType>, MalError> {
+    if arg.is_nil() {
+        Ok(vec![])
+    } else if let Some(map) = arg.hashmap_val() {
+        Ok(map.iter()
+            .map(|(key, val)| MalType::vector(vec![key.clone(), val.clone()]))
+            .collect())
+    } else {
+        vec_result(arg)
+    }
+}
+
+/// The error for an argument of the wrong type, worded like the checks
+/// builtins get from their signatures. `index` counts from 0.
+pub fn arg_error(name: &str, expected: &str, index: usize, arg: &MalType) -> MalError {
+    MalError::WrongArguments(format!(
+        "`{}` expected {} at argument {}, got {}",
+        name,
+        expected,
+        index + 1,
+        pr_str(arg, true)
+    ))
+}
+
+pub fn string_arg<'a>(args: &'a [MalType], index: usize, name: &str) -> Result<&'a str, MalError> {
+    args[index]
+        .string_val()
+        .ok_or_else(|| arg_error(name, "string", index, &args[index]))
+}
+
+/// True when the options map at `index`, if there is one, has a truthy
+/// `key`. Builtins declare the options as `MapOrNil`.
+pub fn option(args: &[MalType], index: usize, key: &str) -> bool {
+    args.get(index)
+        .and_then(|opts| opts.hashmap_val())
+        .and_then(|map| map.get(&MalType::keyword(key)))
+        .is_some_and(|val| !val.is_falsey())
+}
@@ -0,0 +1,824 @@
+//! `serde` support for `MalType`, and `to_value`/`from_value` for moving
+//! data between Rust types and mal.
+
+use types::*;
+
+use serde;
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+use std::any::type_name;
+use std::collections::{btree_map, BTreeMap};
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Keywords, symbols and characters serialize as strings. Functions, atoms
+/// and regexes are not data and fail to serialize.
+impl Serialize for MalType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_nil() {
+            serializer.serialize_unit()
+        } else if self.is_true() {
+            serializer.serialize_bool(true)
+        } else if self.is_false() {
+            serializer.serialize_bool(false)
+        } else if let Some(number) = self.number_val() {
+            serializer.serialize_i64(number)
+        } else if let Some(string) = self.string_val() {
+            serializer.serialize_str(string)
+        } else if let Some(keyword) = self.keyword_val() {
+            serializer.serialize_str(keyword)
+        } else if let Some(symbol) = self.symbol_val() {
+            serializer.serialize_str(symbol)
+        } else if let Some(c) = self.char_val() {
+            serializer.serialize_char(c)
+        } else if let Some(items) = self.list_or_vector_val() {
+            let mut seq = serializer.serialize_seq(Some(items.len()))?;
+            for item in items {
+                seq.serialize_element(item)?;
+            }
+            seq.end()
+        } else if let Some(set) = self.set_val() {
+            let mut seq = serializer.serialize_seq(Some(set.len()))?;
+            for item in set {
+                seq.serialize_element(item)?;
+            }
+            seq.end()
+        } else if let Some(map) = self.hashmap_val() {
+            let mut ser_map = serializer.serialize_map(Some(map.len()))?;
+            for (key, val) in map {
+                ser_map.serialize_entry(key, val)?;
+            }
+            ser_map.end()
+        } else {
+            Err(ser::Error::custom(format!("Cannot serialize {:?}", self)))
+        }
+    }
+}
+
+/// Sequences become vectors and map keys stay as they are, so strings when
+/// reading JSON.
+impl<'de> Deserialize<'de> for MalType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MalType, D::Error> {
+        deserializer.deserialize_any(MalTypeVisitor)
+    }
+}
+
+struct MalTypeVisitor;
+
+impl<'de> Visitor<'de> for MalTypeVisitor {
+    type Value = MalType;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a mal value")
+    }
+
+    fn visit_unit<E>(self) -> Result<MalType, E> {
+        Ok(MalType::nil())
+    }
+
+    fn visit_none<E>(self) -> Result<MalType, E> {
+        Ok(MalType::nil())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<MalType, D::Error> {
+        MalType::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<MalType, E> {
+        Ok(if value {
+            MalType::bool_true()
+        } else {
+            MalType::bool_false()
+        })
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<MalType, E> {
+        Ok(MalType::number(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<MalType, E> {
+        if value > i64::MAX as u64 {
+            Err(E::custom(format!("{} does not fit in a mal number", value)))
+        } else {
+            Ok(MalType::number(value as i64))
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<MalType, E> {
+        Err(E::custom(format!("{} is not an integer", value)))
+    }
+
+    fn visit_char<E>(self, value: char) -> Result<MalType, E> {
+        Ok(MalType::char(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<MalType, E> {
+        Ok(MalType::string(value))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<MalType, E> {
+        Ok(MalType::string(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<MalType, A::Error> {
+        let mut items = vec![];
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(MalType::vector(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<MalType, A::Error> {
+        let mut map = BTreeMap::new();
+        while let Some((key, val)) = access.next_entry()? {
+            map.insert(key, val);
+        }
+        Ok(MalType::hashmap(map))
+    }
+}
+
+/// Converts a Rust value into mal data. Structs and other maps get keyword
+/// keys, so `Point { x: 1, y: 2 }` becomes `{:x 1 :y 2}`, and keys that are
+/// not strings stay as they are. Floats must be whole numbers.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> MalResult {
+    value.serialize(ValueSerializer).map_err(|err| {
+        MalError::WrongArguments(format!(
+            "Cannot convert {} to a mal value: {}",
+            type_name::<T>(),
+            err
+        ))
+    })
+}
+
+/// Converts mal data into a Rust value. Keyword, symbol and string keys all
+/// match struct fields of the same name.
+pub fn from_value<T: DeserializeOwned>(value: &MalType) -> Result<T, MalError> {
+    T::deserialize(ValueDeserializer(value)).map_err(|err| {
+        MalError::WrongArguments(format!(
+            "Cannot convert {:?} to {}: {}",
+            value,
+            type_name::<T>(),
+            err
+        ))
+    })
+}
+
+/// The error `to_value` and `from_value` wrap with what was being converted.
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+/// An enum variant with data becomes `{:Variant data}`.
+fn variant_map(variant: &str, data: MalType) -> MalType {
+    let mut map = BTreeMap::new();
+    map.insert(MalType::keyword(variant), data);
+    MalType::hashmap(map)
+}
+
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = MalType;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, value: bool) -> Result<MalType, Error> {
+        Ok(if value {
+            MalType::bool_true()
+        } else {
+            MalType::bool_false()
+        })
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<MalType, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<MalType, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<MalType, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<MalType, Error> {
+        Ok(MalType::number(value))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<MalType, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<MalType, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<MalType, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<MalType, Error> {
+        if value > i64::MAX as u64 {
+            Err(Error(format!("{} does not fit in a mal number", value)))
+        } else {
+            self.serialize_i64(value as i64)
+        }
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<MalType, Error> {
+        self.serialize_f64(f64::from(value))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<MalType, Error> {
+        // Every whole f64 in this range is exactly an i64.
+        if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+            self.serialize_i64(value as i64)
+        } else {
+            Err(Error(format!("{} is not a whole number", value)))
+        }
+    }
+
+    fn serialize_char(self, value: char) -> Result<MalType, Error> {
+        Ok(MalType::char(value))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<MalType, Error> {
+        Ok(MalType::string(value))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<MalType, Error> {
+        Ok(MalType::vector(
+            value.iter().map(|&byte| MalType::number(i64::from(byte))).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<MalType, Error> {
+        Ok(MalType::nil())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<MalType, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<MalType, Error> {
+        Ok(MalType::nil())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<MalType, Error> {
+        Ok(MalType::nil())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<MalType, Error> {
+        Ok(MalType::string(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<MalType, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<MalType, Error> {
+        Ok(variant_map(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            map: BTreeMap::new(),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            map: BTreeMap::new(),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SeqSerializer {
+    items: Vec<MalType>,
+    /// The variant name for a tuple variant.
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<MalType, Error> {
+        let vector = MalType::vector(self.items);
+        Ok(match self.variant {
+            Some(variant) => variant_map(variant, vector),
+            None => vector,
+        })
+    }
+}
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for SeqSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for SeqSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for SeqSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    map: BTreeMap<MalType, MalType>,
+    /// The key whose value comes next.
+    key: Option<MalType>,
+    /// The variant name for a struct variant.
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn finish(self) -> Result<MalType, Error> {
+        let map = MalType::hashmap(self.map);
+        Ok(match self.variant {
+            Some(variant) => variant_map(variant, map),
+            None => map,
+        })
+    }
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = key.serialize(ValueSerializer)?;
+        self.key = Some(match key.string_val() {
+            Some(name) => MalType::keyword(name),
+            None => key,
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.map.insert(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for MapSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.map.insert(MalType::keyword(key), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for MapSerializer {
+    type Ok = MalType;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<MalType, Error> {
+        self.finish()
+    }
+}
+
+struct ValueDeserializer<'a>(&'a MalType);
+
+impl<'de, 'a> Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = self.0;
+        if value.is_nil() {
+            visitor.visit_unit()
+        } else if value.is_true() {
+            visitor.visit_bool(true)
+        } else if value.is_false() {
+            visitor.visit_bool(false)
+        } else if let Some(number) = value.number_val() {
+            visitor.visit_i64(number)
+        } else if let Some(string) = value.string_val() {
+            visitor.visit_str(string)
+        } else if let Some(keyword) = value.keyword_val() {
+            visitor.visit_str(keyword)
+        } else if let Some(symbol) = value.symbol_val() {
+            visitor.visit_str(symbol)
+        } else if let Some(c) = value.char_val() {
+            visitor.visit_char(c)
+        } else if let Some(items) = value.list_or_vector_val() {
+            visitor.visit_seq(SeqDeserializer(Box::new(items.iter())))
+        } else if let Some(set) = value.set_val() {
+            visitor.visit_seq(SeqDeserializer(Box::new(set.iter())))
+        } else if let Some(map) = value.hashmap_val() {
+            visitor.visit_map(MapDeserializer {
+                entries: map.iter(),
+                value: None,
+            })
+        } else {
+            Err(Error(format!("{:?} is not data", value)))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.0.is_nil() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// A unit variant is a string or keyword, and any other variant a map
+    /// with a single entry, as `to_value` writes them.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let value = self.0;
+        if value.is_string() || value.is_keyword() {
+            return visitor.visit_enum(EnumDeserializer {
+                variant: value,
+                data: None,
+            });
+        }
+        match value.hashmap_val() {
+            Some(map) if map.len() == 1 => {
+                let (variant, data) = map.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    data: Some(data),
+                })
+            }
+            _ => Err(Error(format!("Expected an enum variant but got: {:?}", value))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct SeqDeserializer<'a>(Box<dyn Iterator<Item = &'a MalType> + 'a>);
+
+impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.0.next() {
+            Some(item) => seed.deserialize(ValueDeserializer(item)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapDeserializer<'a> {
+    entries: btree_map::Iter<'a, MalType, MalType>,
+    /// The value for the key just read.
+    value: Option<&'a MalType>,
+}
+
+impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(ValueDeserializer(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(ValueDeserializer(value))
+    }
+}
+
+struct EnumDeserializer<'a> {
+    variant: &'a MalType,
+    data: Option<&'a MalType>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for EnumDeserializer<'a> {
+    type Error = Error;
+    type Variant = VariantDeserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer<'a>), Error> {
+        let variant = seed.deserialize(ValueDeserializer(self.variant))?;
+        Ok((variant, VariantDeserializer(self.data)))
+    }
+}
+
+struct VariantDeserializer<'a>(Option<&'a MalType>);
+
+impl<'a> VariantDeserializer<'a> {
+    fn data(self) -> Result<&'a MalType, Error> {
+        self.0
+            .ok_or_else(|| Error("Expected data for an enum variant".to_string()))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for VariantDeserializer<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.0 {
+            Some(data) if !data.is_nil() => Err(Error(format!(
+                "Expected no data for a unit variant but got: {:?}",
+                data
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(ValueDeserializer(self.data()?))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        ValueDeserializer(self.data()?).deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        ValueDeserializer(self.data()?).deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::read_str;
+    use serde_json;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Person {
+        name: String,
+        age: u32,
+        tags: Vec<String>,
+        nickname: Option<String>,
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        let person = Person {
+            name: "Tim".to_string(),
+            age: 40,
+            tags: vec!["rust".to_string()],
+            nickname: None,
+        };
+        let value = to_value(&person).unwrap();
+        assert_eq!(
+            value,
+            read_str(r#"{:name "Tim" :age 40 :tags ["rust"] :nickname nil}"#).unwrap()
+        );
+        assert_eq!(from_value::<Person>(&value).unwrap(), person);
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let value = read_str(r#"{:name "Tim" :age "forty" :tags []}"#).unwrap();
+        match from_value::<Person>(&value) {
+            Err(MalError::WrongArguments(message)) => {
+                assert!(message.ends_with("invalid type: string \"forty\", expected u32"))
+            }
+            other => panic!("expected wrong arguments, got {:?}", other),
+        }
+        let value = read_str(r#"{:name "Tim"}"#).unwrap();
+        assert!(from_value::<Person>(&value).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let value = to_value(&'x').unwrap();
+        assert_eq!(value, MalType::char('x'));
+        assert_eq!(from_value::<char>(&value).unwrap(), 'x');
+        let value = to_value(&3.0f64).unwrap();
+        assert_eq!(value, MalType::number(3));
+        assert_eq!(from_value::<f64>(&value).unwrap(), 3.0);
+        let mut scores = HashMap::new();
+        scores.insert(1u32, "one".to_string());
+        scores.insert(2u32, "two".to_string());
+        let value = to_value(&scores).unwrap();
+        assert_eq!(value, read_str(r#"{1 "one" 2 "two"}"#).unwrap());
+        assert_eq!(from_value::<HashMap<u32, String>>(&value).unwrap(), scores);
+        let shapes = vec![Shape::Empty, Shape::Circle(2), Shape::Rect { w: 3, h: 4 }];
+        let value = to_value(&shapes).unwrap();
+        assert_eq!(
+            value,
+            read_str(r#"["Empty" {:Circle 2} {:Rect {:w 3 :h 4}}]"#).unwrap()
+        );
+        assert_eq!(from_value::<Vec<Shape>>(&value).unwrap(), shapes);
+    }
+
+    #[test]
+    fn test_numbers_out_of_range() {
+        match to_value(&2.5f64) {
+            Err(MalError::WrongArguments(message)) => {
+                assert!(message.ends_with("2.5 is not a whole number"))
+            }
+            other => panic!("expected wrong arguments, got {:?}", other),
+        }
+        assert!(to_value(&u64::MAX).is_err());
+        assert!(from_value::<u8>(&MalType::number(256)).is_err());
+    }
+
+    #[test]
+    fn test_serialize_mal_type() {
+        let value = read_str(r#"{:a [1 \b nil] "c" #{true}}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"c":[true],"a":[1,"b",null]}"#
+        );
+        let value: MalType = serde_json::from_str(r#"{"a":[1,"b",null]}"#).unwrap();
+        assert_eq!(value, read_str(r#"{"a" [1 "b" nil]}"#).unwrap());
+        assert!(serde_json::to_string(&MalType::atom(MalType::nil())).is_err());
+    }
+}